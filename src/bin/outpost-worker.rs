@@ -1,24 +1,22 @@
-use std::{path::PathBuf, time::Duration};
+use std::path::PathBuf;
 
 use clap::Parser;
-use outpost::{config::Credentials, worker};
+use outpost::worker;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 #[derive(Parser)]
 #[command(name = "outpost-worker", version)]
 enum Cli {
     Poll {
+        /// The path to the configuration file.
         #[arg(long)]
-        on_update: PathBuf,
-
-        #[arg(long)]
-        updates: PathBuf,
-
-        #[arg(long)]
-        iterations: Option<usize>,
-
+        config: PathBuf,
+    },
+    /// Like `poll`, but also check as soon as a push webhook is received.
+    Listen {
+        /// The path to the configuration file.
         #[arg(long)]
-        interval: Option<u64>,
+        config: PathBuf,
     },
 }
 
@@ -26,16 +24,11 @@ fn main() {
     setup_logging();
 
     match Cli::parse() {
-        Cli::Poll {
-            on_update,
-            updates,
-            iterations,
-            interval,
-        } => {
-            let credentials = Credentials::from_env().expect("invalid credentials");
-            let interval = Duration::from_secs(interval.unwrap_or(60));
-            worker::poll(on_update, updates, interval, iterations, credentials)
-                .expect("failed to run `poll`");
+        Cli::Poll { config } => {
+            worker::poll(config).expect("failed to run `poll`");
+        }
+        Cli::Listen { config } => {
+            worker::listen(config).expect("failed to run `listen`");
         }
     }
 
@@ -1,8 +1,8 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use clap::Parser;
+use outpost::cli;
 use outpost::config::Config;
-use outpost::{cli, config::Credentials};
 use time::macros::format_description;
 use tracing_subscriber::{
     fmt::time::UtcTime, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
@@ -16,13 +16,102 @@ enum Command {
         #[arg(long)]
         config: Option<PathBuf>,
     },
-    Stop {},
+    Stop {
+        /// The directory of the worker to stop.
+        path: Option<PathBuf>,
+
+        /// Seconds to wait for the worker to exit before killing it.
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+    Restart {
+        /// The directory of the worker to restart.
+        path: Option<PathBuf>,
+
+        /// The path to the configuration file. Defaults to the one the worker
+        /// was last started with.
+        #[arg(long)]
+        config: Option<PathBuf>,
+
+        /// Seconds to wait for the worker to exit before killing it.
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+    Reload {
+        /// The directory of the worker to reload.
+        path: Option<PathBuf>,
+    },
     Rm {
         /// The key to remove.
         key: PathBuf,
     },
     Ls {
         path: Option<PathBuf>,
+
+        /// How to print the entries.
+        #[arg(long, value_enum, default_value_t = cli::Format::Table)]
+        format: cli::Format,
+    },
+    /// List the updates run by the workers.
+    History {
+        path: Option<PathBuf>,
+
+        /// How to print the updates.
+        #[arg(long, value_enum, default_value_t = cli::Format::Table)]
+        format: cli::Format,
+    },
+    /// Print the output of an update, step by step.
+    Logs {
+        /// The ID of the update, as listed by `history`.
+        id: u64,
+
+        /// Only print the output of the step with this name.
+        #[arg(long)]
+        step: Option<String>,
+
+        /// Print the standard error of the hook instead of its standard output.
+        #[arg(long)]
+        stderr: bool,
+
+        /// Keep printing new output until the hook has finished.
+        #[arg(long, short)]
+        follow: bool,
+    },
+    /// Print the live state of a running worker.
+    Status {
+        /// The directory of the worker.
+        path: Option<PathBuf>,
+
+        /// How to print the state.
+        #[arg(long, value_enum, default_value_t = cli::Format::Table)]
+        format: cli::Format,
+    },
+    /// Make a running worker check for updates right away.
+    Trigger {
+        /// The directory of the worker.
+        path: Option<PathBuf>,
+
+        /// Run the update even if nothing has changed, e.g. to redeploy the
+        /// current commit. Tags are not forced, so this fails when only tags
+        /// are watched.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Make a running worker stop checking for updates until it is resumed.
+    Pause {
+        /// The directory of the worker.
+        path: Option<PathBuf>,
+    },
+    /// Make a paused worker check for updates again.
+    Resume {
+        /// The directory of the worker.
+        path: Option<PathBuf>,
+    },
+    /// Remove the entries of workers that have died without being stopped.
+    Gc {
+        /// Only print the entries that would be removed.
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
@@ -30,34 +119,34 @@ fn main() {
     setup_logging();
     match Command::parse() {
         Command::Start { config } => {
-            let config = config.expect("must provide config");
-            let config = Config::from_path(&config).expect("failed to read config");
-            let credentials = Credentials::from_env().expect("invalid credentials");
-            let stdout = config
-                .stdout
-                .unwrap_or(PathBuf::from("/tmp/outpost.out"))
-                .display()
-                .to_string();
-            let stderr = config
-                .stderr
-                .unwrap_or(PathBuf::from("/tmp/outpost.err"))
-                .display()
-                .to_string();
-            let on_update = config.on_update.display().to_string();
-            let updates = config.updates.display().to_string();
-            cli::start(
-                stdout,
-                stderr,
-                on_update,
-                updates,
-                config.iterations,
-                config.interval,
-                credentials,
-            )
-            .expect("`start` failed");
+            let config_path = config
+                .expect("must provide config")
+                .canonicalize()
+                .expect("failed to canonicalize config path");
+            let config = Config::from_path(&config_path).expect("failed to read config");
+            warn_about_env_credentials();
+            cli::start(&config_path, &config).expect("`start` failed");
+        }
+        Command::Stop { path, timeout } => {
+            let key = key_or_current_dir(path);
+            cli::stop(key, Duration::from_secs(timeout)).expect("`stop` failed");
         }
-        Command::Stop {} => {
-            cli::stop();
+        Command::Restart {
+            path,
+            config,
+            timeout,
+        } => {
+            let key = key_or_current_dir(path);
+            let config_path = config.map(|path| {
+                path.canonicalize()
+                    .expect("failed to canonicalize config path")
+            });
+            warn_about_env_credentials();
+            cli::restart(key, config_path, Duration::from_secs(timeout)).expect("`restart` failed");
+        }
+        Command::Reload { path } => {
+            let key = key_or_current_dir(path);
+            cli::reload(key).expect("`reload` failed");
         }
         Command::Rm { key } => {
             let key = key
@@ -67,17 +156,61 @@ fn main() {
                 .to_string();
             cli::rm(key).expect("`rm` failed");
         }
-        Command::Ls { path } => {
-            let path = path
-                .unwrap_or_else(|| {
-                    std::env::current_dir().expect("failed to find current directory")
-                })
-                .canonicalize()
-                .expect("failed to canonicalize path")
-                .display()
-                .to_string();
-            cli::ls(path.as_str()).expect("`ls` failed");
+        Command::Ls { path, format } => {
+            let path = key_or_current_dir(path);
+            cli::ls(path.as_str(), format).expect("`ls` failed");
+        }
+        Command::History { path, format } => {
+            let path = key_or_current_dir(path);
+            cli::history(path.as_str(), format).expect("`history` failed");
+        }
+        Command::Logs {
+            id,
+            step,
+            stderr,
+            follow,
+        } => {
+            cli::logs(id, step.as_deref(), stderr, follow).expect("`logs` failed");
+        }
+        Command::Status { path, format } => {
+            let key = key_or_current_dir(path);
+            cli::status(key, format).expect("`status` failed");
+        }
+        Command::Trigger { path, force } => {
+            let key = key_or_current_dir(path);
+            cli::trigger(key, force).expect("`trigger` failed");
+        }
+        Command::Pause { path } => {
+            let key = key_or_current_dir(path);
+            cli::pause(key).expect("`pause` failed");
         }
+        Command::Resume { path } => {
+            let key = key_or_current_dir(path);
+            cli::resume(key).expect("`resume` failed");
+        }
+        Command::Gc { dry_run } => {
+            cli::gc(dry_run).expect("`gc` failed");
+        }
+    }
+}
+
+fn key_or_current_dir(path: Option<PathBuf>) -> String {
+    path.unwrap_or_else(|| std::env::current_dir().expect("failed to find current directory"))
+        .canonicalize()
+        .expect("failed to canonicalize path")
+        .display()
+        .to_string()
+}
+
+/// Credentials are no longer passed to workers through their environment.
+fn warn_about_env_credentials() {
+    if ["GIT_USERNAME", "GIT_PASSWORD"]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some())
+    {
+        tracing::warn!(
+            "`GIT_USERNAME` and `GIT_PASSWORD` are not passed to the worker. Set `credentials` in the configuration instead."
+        );
     }
 }
 
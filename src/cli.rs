@@ -1,9 +1,24 @@
+mod control;
+mod gc;
+mod history;
+mod logs;
 mod ls;
+mod reload;
+mod restart;
 mod rm;
 mod start;
+mod status;
 mod stop;
+mod table;
 
-pub use ls::ls;
+pub use control::{pause, resume, trigger};
+pub use gc::gc;
+pub use history::history;
+pub use logs::logs;
+pub use ls::{ls, Format};
+pub use reload::reload;
+pub use restart::restart;
 pub use rm::rm;
 pub use start::start;
+pub use status::status;
 pub use stop::stop;
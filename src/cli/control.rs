@@ -0,0 +1,27 @@
+use crate::control::{self, ControlError, Request, Response};
+
+/// Make the worker in `key` check for updates right away. With `force`, the
+/// update is run even if nothing has changed, and recorded as manual.
+pub fn trigger(key: String, force: bool) -> Result<(), ControlError> {
+    request(&key, Request::Trigger { force })
+}
+
+/// Make the worker in `key` stop checking for updates until it is resumed.
+pub fn pause(key: String) -> Result<(), ControlError> {
+    request(&key, Request::Pause)
+}
+
+/// Make the paused worker in `key` check for updates again, starting right
+/// away.
+pub fn resume(key: String) -> Result<(), ControlError> {
+    request(&key, Request::Resume)
+}
+
+fn request(key: &str, request: Request) -> Result<(), ControlError> {
+    tracing::debug!(?request, "Sending request to the worker in `{key}`.");
+
+    match control::send(key, request)? {
+        Response::Ok => Ok(()),
+        response => Err(ControlError::UnexpectedResponse(response)),
+    }
+}
@@ -0,0 +1,43 @@
+use crate::database::{self, Process, ProcessState, PROCESSES};
+
+#[derive(Debug)]
+pub enum GcError {
+    HomeDirectoryMissing,
+    Database(sled::Error),
+}
+
+/// Remove the entries, and statuses, of worker processes that have exited
+/// without being stopped, or whose process ID has been reused by an
+/// unrelated process.
+pub fn gc(dry_run: bool) -> Result<(), GcError> {
+    let outpost_dir = home::home_dir()
+        .ok_or(GcError::HomeDirectoryMissing)?
+        .join(".outpost");
+
+    let database_dir = outpost_dir.join("database");
+
+    let db = database::open(&database_dir).map_err(GcError::Database)?;
+    let processes = db.open_tree(PROCESSES).map_err(GcError::Database)?;
+
+    let dead: Vec<_> = processes
+        .iter()
+        .filter_map(|b| {
+            let (key, value) = b.expect("invalid entry");
+            let process = Process::from_slice(value.as_ref()).expect("valid json");
+            (process.state() == ProcessState::Dead).then_some(key)
+        })
+        .collect();
+
+    for key in dead {
+        let directory = String::from_utf8_lossy(&key);
+
+        if dry_run {
+            println!("Would remove {directory}");
+        } else {
+            database::remove_process(&db, &key).map_err(GcError::Database)?;
+            println!("Removed {directory}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use serde::Serialize;
+
+use super::{
+    table::{dash, format_duration, format_timestamp, optional, print_table},
+    Format,
+};
+use crate::database::{self, v1, Update, UpdateResult, UPDATES};
+
+#[derive(Debug)]
+pub enum HistoryError {
+    HomeDirectoryMissing,
+    Database(sled::Error),
+}
+
+#[derive(Debug, Serialize)]
+struct Row {
+    id: u64,
+    directory: String,
+    old_commit: Option<String>,
+    new_commit: String,
+    branch: Option<String>,
+    tag: Option<String>,
+    started_at: i64,
+    finished_at: Option<i64>,
+    exit_code: Option<i32>,
+    /// `None` while the hook is running.
+    result: Option<UpdateResult>,
+    /// Forced with `outpost trigger --force`.
+    manual: bool,
+    steps: Vec<v1::Step>,
+}
+
+impl Row {
+    fn new(id: u64, update: &Update) -> Self {
+        Self {
+            id,
+            directory: update.directory().to_string(),
+            old_commit: update.old_commit().map(String::from),
+            new_commit: update.new_commit().to_string(),
+            branch: update.branch().map(String::from),
+            tag: update.tag().map(String::from),
+            started_at: update.started_at(),
+            finished_at: update.finished_at(),
+            exit_code: update.exit_code(),
+            result: update.result(),
+            manual: update.manual(),
+            steps: update.steps().to_vec(),
+        }
+    }
+}
+
+/// Print every recorded update of the workers under `path`, oldest first.
+pub fn history(path: &str, format: Format) -> Result<(), HistoryError> {
+    let outpost_dir = home::home_dir()
+        .ok_or(HistoryError::HomeDirectoryMissing)?
+        .join(".outpost");
+
+    let database_dir = outpost_dir.join("database");
+
+    let db = database::open(&database_dir).map_err(HistoryError::Database)?;
+    let updates = db.open_tree(UPDATES).map_err(HistoryError::Database)?;
+
+    let rows: Vec<_> = updates
+        .iter()
+        .map(|b| {
+            let (key, value) = b.expect("invalid entry");
+            let id = u64::from_be_bytes(key.as_ref().try_into().expect("invalid key"));
+            let update = Update::from_slice(value.as_ref()).expect("valid json");
+            Row::new(id, &update)
+        })
+        .filter(|row| row.directory.starts_with(path))
+        .collect();
+
+    match format {
+        Format::Table => print_rows(&rows),
+        Format::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&rows).expect("failed to serialize rows")
+            );
+        }
+        Format::JsonLines => {
+            for row in &rows {
+                println!(
+                    "{}",
+                    serde_json::to_string(row).expect("failed to serialize row")
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_rows(rows: &[Row]) {
+    let header = [
+        "ID",
+        "DIRECTORY",
+        "REF",
+        "COMMIT",
+        "STARTED",
+        "DURATION",
+        "EXIT CODE",
+        "RESULT",
+    ];
+
+    let cells: Vec<[String; 8]> = rows
+        .iter()
+        .map(|row| {
+            [
+                row.id.to_string(),
+                row.directory.clone(),
+                optional(row.branch.as_ref().or(row.tag.as_ref())),
+                row.new_commit.chars().take(7).collect(),
+                format_timestamp(row.started_at),
+                row.finished_at
+                    .map(|finished_at| format_duration(finished_at - row.started_at))
+                    .unwrap_or_else(dash),
+                optional(row.exit_code),
+                format_result(row),
+            ]
+        })
+        .collect();
+
+    print_table(header, &cells);
+}
+
+fn format_result(row: &Row) -> String {
+    let result = row
+        .result
+        .map(|result| result.to_string())
+        .unwrap_or_else(|| String::from("running"));
+
+    if row.manual {
+        format!("{result} (manual)")
+    } else {
+        result
+    }
+}
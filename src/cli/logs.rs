@@ -0,0 +1,102 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::database::{self, update_key, Update, UPDATES};
+
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum LogsError {
+    HomeDirectoryMissing,
+    UpdateNotFound,
+    StepNotFound,
+    Read(io::Error),
+    Database(sled::Error),
+}
+
+/// Print the output of the update with the given ID, or of one of its steps.
+///
+/// With `follow`, keep printing new output until the update has finished.
+pub fn logs(id: u64, step: Option<&str>, stderr: bool, follow: bool) -> Result<(), LogsError> {
+    let outpost_dir = home::home_dir()
+        .ok_or(LogsError::HomeDirectoryMissing)?
+        .join(".outpost");
+
+    let database_dir = outpost_dir.join("database");
+
+    let mut output = io::stdout().lock();
+
+    // The step being printed, and its output file once it has been opened.
+    let mut index = 0;
+    let mut file = None;
+
+    loop {
+        // Read before copying the output, so that output written right before
+        // a step finished is not lost.
+        let update = read_update(&database_dir, id)?;
+        let steps: Vec<_> = update
+            .steps()
+            .iter()
+            .filter(|s| step.map_or(true, |name| s.name == name))
+            .collect();
+
+        if !follow && steps.is_empty() && step.is_some() {
+            return Err(LogsError::StepNotFound);
+        }
+
+        while let Some(current) = steps.get(index) {
+            let reader = match &mut file {
+                Some(file) => file,
+                None => {
+                    let path = if stderr {
+                        &current.stderr
+                    } else {
+                        &current.stdout
+                    };
+                    let opened = match File::open(path) {
+                        Ok(opened) => opened,
+                        // The step has been recorded, but not started yet.
+                        Err(error) if follow && error.kind() == io::ErrorKind::NotFound => break,
+                        Err(error) => return Err(LogsError::Read(error)),
+                    };
+                    if step.is_none() {
+                        writeln!(output, "==> {} <==", current.name).map_err(LogsError::Read)?;
+                    }
+                    file.insert(opened)
+                }
+            };
+
+            io::copy(reader, &mut output).map_err(LogsError::Read)?;
+
+            if follow && current.finished_at.is_none() {
+                break;
+            }
+
+            index += 1;
+            file = None;
+        }
+
+        if !follow || (update.finished_at().is_some() && index >= steps.len()) {
+            return Ok(());
+        }
+
+        std::thread::sleep(FOLLOW_INTERVAL);
+    }
+}
+
+/// Read the update record, without keeping the database open.
+fn read_update(database_dir: &Path, id: u64) -> Result<Update, LogsError> {
+    let db = database::open(database_dir).map_err(LogsError::Database)?;
+    let updates = db.open_tree(UPDATES).map_err(LogsError::Database)?;
+
+    let value = updates
+        .get(update_key(id))
+        .map_err(LogsError::Database)?
+        .ok_or(LogsError::UpdateNotFound)?;
+
+    Ok(Update::from_slice(value.as_ref()).expect("valid json"))
+}
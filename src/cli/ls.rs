@@ -1,4 +1,11 @@
-use crate::database::{Process, PROCESSES};
+use clap::ValueEnum;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use super::table::{dash, format_duration, format_timestamp, optional, print_table};
+use crate::database::{
+    self, FailureState, Process, ProcessState, Status, UpdateResult, PROCESSES, STATUSES,
+};
 
 #[derive(Debug)]
 pub enum LsError {
@@ -6,31 +13,131 @@ pub enum LsError {
     Database(sled::Error),
 }
 
-pub fn ls(path: &str) -> Result<(), LsError> {
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    JsonLines,
+}
+
+#[derive(Debug, Serialize)]
+struct Row {
+    directory: String,
+    state: ProcessState,
+    process_id: Option<u32>,
+    /// Seconds since the worker was started, if it is running.
+    uptime: Option<i64>,
+    last_update: Option<i64>,
+    last_result: Option<UpdateResult>,
+    /// `None` unless the last check of the worker has failed.
+    failure: Option<FailureState>,
+}
+
+impl Row {
+    fn new(directory: String, process: &Process, status: Option<&Status>, now: i64) -> Self {
+        let state = process.state();
+
+        let uptime = match state {
+            ProcessState::Running => process.started_at().map(|started_at| now - started_at),
+            ProcessState::Stopped | ProcessState::Dead => None,
+        };
+
+        Self {
+            directory,
+            state,
+            process_id: process.process_id(),
+            uptime,
+            last_update: status.and_then(Status::last_update),
+            last_result: status.and_then(Status::last_result),
+            failure: status.and_then(Status::failure).cloned(),
+        }
+    }
+}
+
+pub fn ls(path: &str, format: Format) -> Result<(), LsError> {
     let outpost_dir = home::home_dir()
         .ok_or(LsError::HomeDirectoryMissing)?
         .join(".outpost");
 
     let database_dir = outpost_dir.join("database");
 
-    let processes = sled::open(&database_dir)
-        .map_err(LsError::Database)?
-        .open_tree(PROCESSES)
-        .map_err(LsError::Database)?;
+    let db = database::open(&database_dir).map_err(LsError::Database)?;
+    let processes = db.open_tree(PROCESSES).map_err(LsError::Database)?;
+    let statuses = db.open_tree(STATUSES).map_err(LsError::Database)?;
 
-    let values: Vec<_> = processes
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    let rows: Vec<_> = processes
         .scan_prefix(path)
         .map(|b| {
             let (key, value) = b.expect("invalid entry");
+            let status = statuses
+                .get(&key)
+                .expect("invalid entry")
+                .map(|value| Status::from_slice(value.as_ref()).expect("valid json"));
             let key = String::from_utf8(key.to_vec()).expect("valid utf8");
-            let value: Process = serde_json::from_slice(value.as_ref()).expect("valid json");
-            (key, value)
+            let value = Process::from_slice(value.as_ref()).expect("valid json");
+            Row::new(key, &value, status.as_ref(), now)
         })
         .collect();
 
-    for (key, process) in values {
-        println!("{key}: {process:?}");
+    match format {
+        Format::Table => print_rows(&rows),
+        Format::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&rows).expect("failed to serialize rows")
+            );
+        }
+        Format::JsonLines => {
+            for row in &rows {
+                println!(
+                    "{}",
+                    serde_json::to_string(row).expect("failed to serialize row")
+                );
+            }
+        }
     }
 
     Ok(())
 }
+
+fn print_rows(rows: &[Row]) {
+    let header = [
+        "DIRECTORY",
+        "STATE",
+        "PID",
+        "UPTIME",
+        "LAST UPDATE",
+        "LAST RESULT",
+        "FAILURES",
+    ];
+
+    let cells: Vec<[String; 7]> = rows
+        .iter()
+        .map(|row| {
+            [
+                row.directory.clone(),
+                row.state.to_string(),
+                optional(row.process_id),
+                row.uptime.map(format_duration).unwrap_or_else(dash),
+                row.last_update.map(format_timestamp).unwrap_or_else(dash),
+                optional(row.last_result),
+                row.failure
+                    .as_ref()
+                    .map(format_failure)
+                    .unwrap_or_else(dash),
+            ]
+        })
+        .collect();
+
+    print_table(header, &cells);
+}
+
+fn format_failure(failure: &FailureState) -> String {
+    if failure.paused {
+        format!("{} (paused)", failure.consecutive_failures)
+    } else {
+        failure.consecutive_failures.to_string()
+    }
+}
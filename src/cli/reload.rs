@@ -0,0 +1,46 @@
+use std::io;
+
+use crate::{
+    database::{self, Process, ProcessState, PROCESSES},
+    system::{send_signal, Signal},
+};
+
+#[derive(Debug)]
+pub enum ReloadError {
+    KeyNotPresent,
+    HomeDirectoryMissing,
+    ProcessNotRunning,
+    Signal(io::Error),
+    Database(sled::Error),
+}
+
+pub fn reload(key: String) -> Result<(), ReloadError> {
+    let outpost_dir = home::home_dir()
+        .ok_or(ReloadError::HomeDirectoryMissing)?
+        .join(".outpost");
+
+    let database_dir = outpost_dir.join("database");
+
+    let processes = database::open(&database_dir)
+        .map_err(ReloadError::Database)?
+        .open_tree(PROCESSES)
+        .map_err(ReloadError::Database)?;
+
+    let process = processes
+        .get(key.as_bytes())
+        .map_err(ReloadError::Database)?
+        .ok_or(ReloadError::KeyNotPresent)?;
+
+    let process = Process::from_slice(&process).expect("invalid JSON");
+
+    let id = process
+        .process_id()
+        .filter(|_| process.state() == ProcessState::Running)
+        .ok_or(ReloadError::ProcessNotRunning)?;
+
+    tracing::debug!("Sending SIGHUP to worker process (ID: {id}).");
+
+    send_signal(id, Signal::Hangup).map_err(ReloadError::Signal)?;
+
+    Ok(())
+}
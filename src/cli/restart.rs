@@ -0,0 +1,80 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    cli::{
+        start::{spawn, StartError},
+        stop::{stop, StopError},
+    },
+    config::{Config, ConfigError},
+    database::{self, Process, PROCESSES},
+};
+
+#[derive(Debug)]
+pub enum RestartError {
+    KeyNotPresent,
+    HomeDirectoryMissing,
+    ConfigPathMissing,
+    Config(ConfigError),
+    Stop(StopError),
+    Start(StartError),
+    Database(sled::Error),
+}
+
+/// Stop the worker registered for `key` and start a new one in its place.
+///
+/// When `config_path` is not given, the configuration file the worker was
+/// last started with is read again.
+pub fn restart(
+    key: String,
+    config_path: Option<PathBuf>,
+    timeout: Duration,
+) -> Result<(), RestartError> {
+    let outpost_dir = home::home_dir()
+        .ok_or(RestartError::HomeDirectoryMissing)?
+        .join(".outpost");
+
+    let database_dir = outpost_dir.join("database");
+
+    let processes = database::open(&database_dir)
+        .map_err(RestartError::Database)?
+        .open_tree(PROCESSES)
+        .map_err(RestartError::Database)?;
+
+    let process = processes
+        .get(key.as_bytes())
+        .map_err(RestartError::Database)?
+        .ok_or(RestartError::KeyNotPresent)?;
+
+    let process = Process::from_slice(&process).expect("invalid JSON");
+
+    drop(processes);
+
+    let config_path = config_path
+        .or_else(|| process.config_path().map(PathBuf::from))
+        .ok_or(RestartError::ConfigPathMissing)?;
+
+    let config = Config::from_path(&config_path).map_err(RestartError::Config)?;
+
+    match stop(key.clone(), timeout) {
+        Ok(()) | Err(StopError::ProcessNotRunning) => {}
+        Err(error) => return Err(RestartError::Stop(error)),
+    }
+
+    let process = spawn(&key, &config_path, &config).map_err(RestartError::Start)?;
+
+    let processes = database::open(&database_dir)
+        .map_err(RestartError::Database)?
+        .open_tree(PROCESSES)
+        .map_err(RestartError::Database)?;
+
+    processes
+        .insert(
+            key.as_bytes(),
+            serde_json::to_vec(&process).expect("failed to serialize process"),
+        )
+        .map_err(RestartError::Database)?;
+
+    tracing::debug!("Worker process registered.");
+
+    Ok(())
+}
@@ -1,7 +1,4 @@
-use crate::{
-    database::{Process, PROCESSES},
-    system::is_process_running,
-};
+use crate::database::{self, Process, ProcessState, PROCESSES};
 
 #[derive(Debug)]
 pub enum RmError {
@@ -18,28 +15,23 @@ pub fn rm(key: String) -> Result<(), RmError> {
 
     let database_dir = outpost_dir.join("database");
 
-    let processes = sled::open(database_dir)
-        .map_err(RmError::Database)?
-        .open_tree(PROCESSES)
-        .map_err(RmError::Database)?;
+    let db = database::open(&database_dir).map_err(RmError::Database)?;
+    let processes = db.open_tree(PROCESSES).map_err(RmError::Database)?;
 
     let process = processes
         .get(key.as_bytes())
         .map_err(RmError::Database)?
         .ok_or(RmError::KeyNotPresent)?;
 
-    let process: Process = serde_json::from_slice(&process).expect("invalid JSON");
+    let process = Process::from_slice(&process).expect("invalid JSON");
 
-    if let Some(id) = process.process_id() {
-        if is_process_running(id) {
-            return Err(RmError::ProcessRunning);
-        }
+    if process.state() == ProcessState::Running {
+        return Err(RmError::ProcessRunning);
     }
 
-    processes
-        .remove(key.as_bytes())
-        .map_err(RmError::Database)?
-        .ok_or(RmError::KeyNotPresent)?;
+    if !database::remove_process(&db, key.as_bytes()).map_err(RmError::Database)? {
+        return Err(RmError::KeyNotPresent);
+    }
 
     Ok(())
 }
@@ -1,8 +1,9 @@
-use std::{fs::File, io, process::Command};
+use std::{fs::File, io, path::Path, process::Command};
 
 use crate::{
-    config::Credentials,
-    database::{v1, Process, PROCESSES},
+    config::Config,
+    database::{self, v2, Process, ProcessState, PROCESSES},
+    system::process_identity,
 };
 
 #[derive(Debug)]
@@ -22,15 +23,10 @@ const OUTPOST_WORKER: &str = "./target/debug/outpost-worker";
 #[cfg(not(debug_assertions))]
 const OUTPOST_WORKER: &str = "outpost-worker";
 
-pub fn start(
-    stdout: String,
-    stderr: String,
-    on_update: String,
-    updates: String,
-    iterations: Option<usize>,
-    interval: Option<u64>,
-    credentials: Option<Credentials>,
-) -> Result<(), StartError> {
+const DEFAULT_STDOUT: &str = "/tmp/outpost.out";
+const DEFAULT_STDERR: &str = "/tmp/outpost.err";
+
+pub fn start(config_path: &Path, config: &Config) -> Result<(), StartError> {
     let outpost_dir = home::home_dir()
         .ok_or(StartError::HomeDirectoryMissing)?
         .join(".outpost");
@@ -42,7 +38,7 @@ pub fn start(
         .display()
         .to_string();
 
-    let processes = sled::open(&database_dir)
+    let processes = database::open(&database_dir)
         .map_err(StartError::Database)?
         .open_tree(PROCESSES)
         .map_err(StartError::Database)?;
@@ -50,14 +46,64 @@ pub fn start(
     let entry = processes
         .get(current_dir.as_bytes())
         .map_err(StartError::Database)?
-        .map(|value| serde_json::from_slice(value.as_ref()).expect("valid json"));
+        .map(|value| Process::from_slice(value.as_ref()).expect("valid json"));
 
     drop(processes);
 
     if let Some(process) = entry {
-        return Err(StartError::ExistingEntry(process));
+        match process.state() {
+            ProcessState::Running => return Err(StartError::ExistingEntry(process)),
+            ProcessState::Stopped => {
+                tracing::debug!("Replacing entry of stopped worker process.");
+            }
+            ProcessState::Dead => {
+                tracing::warn!("Replacing stale entry of dead worker process.");
+            }
+        }
     }
 
+    let process = spawn(&current_dir, config_path, config)?;
+
+    let processes = database::open(&database_dir)
+        .map_err(StartError::Database)?
+        .open_tree(PROCESSES)
+        .map_err(StartError::Database)?;
+
+    processes
+        .insert(
+            current_dir.as_bytes(),
+            serde_json::to_vec(&process).expect("failed to serialize process"),
+        )
+        .expect("failed to insert process");
+
+    tracing::debug!("Worker process registered.");
+
+    Ok(())
+}
+
+/// Spawn a worker process in `directory` and describe it as a database entry.
+///
+/// The caller is responsible for registering the returned process.
+pub(crate) fn spawn(
+    directory: &str,
+    config_path: &Path,
+    config: &Config,
+) -> Result<Process, StartError> {
+    let stdout = config
+        .stdout
+        .as_deref()
+        .unwrap_or(Path::new(DEFAULT_STDOUT))
+        .display()
+        .to_string();
+    let stderr = config
+        .stderr
+        .as_deref()
+        .unwrap_or(Path::new(DEFAULT_STDERR))
+        .display()
+        .to_string();
+
+    let version = worker_version();
+
     tracing::debug!("Starting worker process.");
 
     let worker = {
@@ -66,55 +112,59 @@ pub fn start(
 
         let mut command = Command::new(OUTPOST_WORKER);
 
+        // Webhooks are only received in `listen` mode, which polls as well.
+        let mode = if config.listen.is_some() {
+            "listen"
+        } else {
+            "poll"
+        };
+
         command
-            .args([
-                "poll",
-                "--on-update",
-                on_update.as_str(),
-                "--updates",
-                updates.as_str(),
-            ])
+            .arg(mode)
+            .arg("--config")
+            .arg(config_path)
+            .current_dir(directory)
             .stdout(stdout)
-            .stderr(stderr);
-
-        if let Some(n) = iterations {
-            command.args(["--iterations", n.to_string().as_str()]);
-        }
-
-        if let Some(interval) = interval {
-            command.args(["--interval", interval.to_string().as_str()]);
-        }
-
-        if let Some(c) = credentials {
-            command.env("GIT_USERNAME", c.username);
-            command.env("GIT_PASSWORD", c.password);
-        }
+            .stderr(stderr)
+            // Anyone who can read the environment of the worker could read
+            // them. The worker reads `credentials` from the configuration
+            // instead.
+            .env_remove("GIT_USERNAME")
+            .env_remove("GIT_PASSWORD");
 
         command.spawn().map_err(StartError::Spawn)?
     };
 
     tracing::debug!("Worker process started (ID: {}).", worker.id());
 
-    let process = Process::V1(v1::Process {
-        directory: current_dir.to_string(),
+    Ok(Process::V2(Box::new(v2::Process {
+        directory: directory.to_string(),
         stdout,
         stderr,
         process_id: Some(worker.id()),
-    });
-
-    let processes = sled::open(&database_dir)
-        .map_err(StartError::Database)?
-        .open_tree(PROCESSES)
-        .map_err(StartError::Database)?;
-
-    processes
-        .insert(
-            current_dir.as_bytes(),
-            serde_json::to_vec(&process).expect("failed to serialize process"),
-        )
-        .expect("failed to insert process");
+        config_path: Some(config_path.display().to_string()),
+        config: Some(config.into()),
+        started_at: Some(time::OffsetDateTime::now_utc().unix_timestamp()),
+        version,
+        identity: process_identity(worker.id()),
+    })))
+}
 
-    tracing::debug!("Worker process registered.");
+/// The version of the worker binary, which may differ from that of the CLI.
+fn worker_version() -> Option<String> {
+    let output = match Command::new(OUTPOST_WORKER).arg("--version").output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            tracing::warn!(status = %output.status, "Failed to get the version of the worker");
+            return None;
+        }
+        Err(error) => {
+            tracing::warn!(?error, "Failed to get the version of the worker");
+            return None;
+        }
+    };
 
-    Ok(())
+    // Printed as `outpost-worker <version>`.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.split_whitespace().nth(1).map(str::to_string)
 }
@@ -0,0 +1,57 @@
+use time::OffsetDateTime;
+
+use super::{
+    table::{format_duration, format_timestamp, optional, print_table},
+    Format,
+};
+use crate::control::{self, ControlError, Request, Response, WorkerState, WorkerStatus};
+
+/// Print the live state of the worker in `key`, as told by the worker itself.
+pub fn status(key: String, format: Format) -> Result<(), ControlError> {
+    let status = match control::send(&key, Request::Status)? {
+        Response::Status(status) => status,
+        response => return Err(ControlError::UnexpectedResponse(response)),
+    };
+
+    match format {
+        Format::Table => print_status(&status),
+        Format::Json | Format::JsonLines => println!(
+            "{}",
+            serde_json::to_string(&status).expect("failed to serialize status")
+        ),
+    }
+
+    Ok(())
+}
+
+fn print_status(status: &WorkerStatus) {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    let state = match status.state {
+        WorkerState::Idle => "idle",
+        WorkerState::Checking => "checking",
+        WorkerState::Paused => "paused",
+    };
+    let next_check = status
+        .next_check
+        .map(|next_check| format!("in {}", format_duration((next_check - now).max(0))));
+
+    print_table(
+        [
+            "DIRECTORY",
+            "PID",
+            "STATE",
+            "LAST CHECK",
+            "NEXT CHECK",
+            "FAILURES",
+        ],
+        &[[
+            status.directory.clone(),
+            status.process_id.to_string(),
+            state.to_string(),
+            optional(status.last_check.map(format_timestamp)),
+            optional(next_check),
+            status.consecutive_failures.to_string(),
+        ]],
+    );
+}
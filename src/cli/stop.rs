@@ -1 +1,92 @@
-pub fn stop() {}
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    database::{self, Process, ProcessState, PROCESSES},
+    system::{is_process_running, send_signal, Signal},
+};
+
+#[derive(Debug)]
+pub enum StopError {
+    KeyNotPresent,
+    HomeDirectoryMissing,
+    ProcessNotRunning,
+    Signal(io::Error),
+    Timeout,
+    Database(sled::Error),
+}
+
+pub fn stop(key: String, timeout: Duration) -> Result<(), StopError> {
+    let outpost_dir = home::home_dir()
+        .ok_or(StopError::HomeDirectoryMissing)?
+        .join(".outpost");
+
+    let database_dir = outpost_dir.join("database");
+
+    let processes = database::open(&database_dir)
+        .map_err(StopError::Database)?
+        .open_tree(PROCESSES)
+        .map_err(StopError::Database)?;
+
+    let process = processes
+        .get(key.as_bytes())
+        .map_err(StopError::Database)?
+        .ok_or(StopError::KeyNotPresent)?;
+
+    let mut process = Process::from_slice(&process).expect("invalid JSON");
+
+    drop(processes);
+
+    let id = process.process_id().ok_or(StopError::ProcessNotRunning)?;
+
+    if process.state() == ProcessState::Running {
+        tracing::debug!("Sending SIGTERM to worker process (ID: {id}).");
+
+        send_signal(id, Signal::Terminate).map_err(StopError::Signal)?;
+
+        if !wait_for_exit(id, timeout) {
+            tracing::warn!("Worker process did not exit in time (ID: {id}). Sending SIGKILL.");
+
+            send_signal(id, Signal::Kill).map_err(StopError::Signal)?;
+
+            if !wait_for_exit(id, timeout) {
+                return Err(StopError::Timeout);
+            }
+        }
+
+        tracing::debug!("Worker process stopped (ID: {id}).");
+    } else {
+        tracing::warn!("Worker process is no longer running (ID: {id}).");
+    }
+
+    process.clear_process_id();
+
+    let processes = database::open(&database_dir)
+        .map_err(StopError::Database)?
+        .open_tree(PROCESSES)
+        .map_err(StopError::Database)?;
+
+    processes
+        .insert(
+            key.as_bytes(),
+            serde_json::to_vec(&process).expect("failed to serialize process"),
+        )
+        .map_err(StopError::Database)?;
+
+    Ok(())
+}
+
+fn wait_for_exit(id: u32, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+
+    while is_process_running(id) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    true
+}
@@ -0,0 +1,52 @@
+use time::{macros::format_description, OffsetDateTime};
+
+/// Print `cells` under `header`, with every column padded to its widest cell.
+pub fn print_table<const N: usize>(header: [&str; N], cells: &[[String; N]]) {
+    let header = header.map(String::from);
+
+    let mut widths = header.clone().map(|h| h.len());
+    for row in cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(cells) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+pub fn optional(value: Option<impl ToString>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(dash)
+}
+
+pub fn dash() -> String {
+    String::from("-")
+}
+
+pub fn format_duration(seconds: i64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+
+    if days > 0 {
+        format!("{days}d{hours}h")
+    } else if hours > 0 {
+        format!("{hours}h{minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+pub fn format_timestamp(timestamp: i64) -> String {
+    let format = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|t| t.format(format).ok())
+        .unwrap_or_else(dash)
+}
@@ -1,21 +1,476 @@
 use std::{
+    collections::BTreeMap,
     io,
+    net::SocketAddr,
+    os::unix::fs::{MetadataExt, PermissionsExt},
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use semver::{Version, VersionReq};
+
+use crate::{
+    netrc::{Netrc, NetrcError},
+    pattern::Pattern,
+    units::{Age, Size},
+};
+
+const DEFAULT_SHELL: &[&str] = &["/bin/sh", "-c"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub stdout: Option<PathBuf>,
     pub stderr: Option<PathBuf>,
-    pub on_update: PathBuf,
+    /// Run for every update. Either this or `steps` must be given.
+    pub on_update: Option<HookCommand>,
+    /// Run one after the other for every update, instead of `on_update`.
+    #[serde(default)]
+    pub steps: Vec<StepConfig>,
+    /// Run when an update fails, with the commit of the last successful
+    /// update as argument.
+    pub on_failure: Option<HookCommand>,
+    /// Whether to reset the branch that HEAD points to, and the working tree,
+    /// to the commit of the last successful update when an update of that
+    /// branch fails. Not supported with `branches` or `tags`.
+    #[serde(default)]
+    pub rollback: bool,
     pub updates: PathBuf,
+    /// The program, and its arguments, that runs hooks written as
+    /// `{ shell = "..." }`, with the script as the last argument. Defaults to
+    /// `["/bin/sh", "-c"]`. Unlike those hooks, this is not a script.
+    pub shell_program: Option<Vec<String>>,
     pub iterations: Option<usize>,
     pub interval: Option<u64>,
+    /// Seconds after which a running hook, and every process it started, is
+    /// terminated.
+    pub hook_timeout: Option<u64>,
+    /// The remote to fetch from. Defaults to the default remote of the
+    /// repository, usually `origin`.
+    pub remote: Option<String>,
+    /// The branch on the remote to watch, instead of the one tracked by the
+    /// branch that HEAD points to. Required if HEAD is detached.
+    pub branch: Option<String>,
+    /// Remote branches to watch instead of the branch that HEAD points to.
+    #[serde(default)]
+    pub branches: Vec<BranchConfig>,
+    /// A reference of a remote repository to watch without a checkout,
+    /// instead of anything in the current repository.
+    pub watch: Option<WatchConfig>,
+    /// Tags to watch on the remote.
+    pub tags: Option<TagConfig>,
+    /// How the branch that HEAD points to is brought up to date. Must be
+    /// `hook` with `branches` or `tags`.
+    #[serde(default)]
+    pub update_strategy: UpdateStrategy,
+    /// What the worker does when a check fails.
+    #[serde(default)]
+    pub failure: FailureConfig,
+    /// Which update directories to delete after an update.
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    /// Receive push webhooks when the worker runs in `listen` mode.
+    pub listen: Option<ListenConfig>,
+    /// Where to send notifications about updates.
+    pub notify: Option<NotifyConfig>,
+    /// How to connect to SSH remotes.
+    pub ssh: Option<SshConfig>,
+    /// Credentials for HTTP remotes. Without them, the credential helpers
+    /// configured for Git are asked.
+    pub credentials: Option<CredentialsConfig>,
 }
 
-#[derive(Debug)]
+/// Only the references of the repository are listed, so the worker does not
+/// need to run in a checkout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchConfig {
+    /// The URL of the remote repository.
+    pub url: String,
+    /// The reference to watch, e.g. `main`, `refs/heads/main` or
+    /// `refs/tags/latest`. Names without `refs/` are branches.
+    #[serde(rename = "ref")]
+    pub reference: String,
+}
+
+impl WatchConfig {
+    /// The full name of the watched reference.
+    pub fn full_name(&self) -> String {
+        if self.reference.starts_with("refs/") {
+            self.reference.clone()
+        } else {
+            format!("refs/heads/{}", self.reference)
+        }
+    }
+}
+
+/// The HTTP server that receives push webhooks. Every verified push triggers
+/// a check right away; `interval` still applies as a fallback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListenConfig {
+    /// The address to listen on, e.g. `127.0.0.1:8080`.
+    pub address: SocketAddr,
+    /// The path that webhooks are sent to. Defaults to `/`.
+    #[serde(default = "default_listen_path")]
+    pub path: String,
+    /// The secret configured for the webhook. Used to verify the signature
+    /// of GitHub and Gitea webhooks, and compared with the token of GitLab
+    /// webhooks.
+    ///
+    /// Left out when the configuration is stored with the worker entry, and
+    /// empty when it is read back from there.
+    #[serde(default, skip_serializing)]
+    pub secret: String,
+}
+
+fn default_listen_path() -> String {
+    "/".to_string()
+}
+
+/// Secrets read from a file by the worker when it starts or is reloaded, so
+/// they are neither stored with the configuration nor passed to the worker.
+/// The file must be owned by the user, and not be accessible by anyone else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CredentialsConfig {
+    /// A token, e.g. a personal access token, used as the password.
+    Token {
+        username: String,
+        token_file: PathBuf,
+    },
+    /// A username and a password, written as TOML with `username` and
+    /// `password` keys if the file name ends with `.toml`, or in `.netrc`
+    /// format otherwise.
+    File { file: PathBuf },
+}
+
+#[derive(Deserialize)]
+struct CredentialsFile {
+    username: String,
+    password: String,
+}
+
+impl CredentialsConfig {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Token { token_file, .. } => token_file,
+            Self::File { file } => file,
+        }
+    }
+
+    /// Whether the file is in `.netrc` format, with an entry per host.
+    pub fn is_netrc(&self) -> bool {
+        match self {
+            Self::Token { .. } => false,
+            Self::File { file } => file.extension().map_or(true, |e| e != "toml"),
+        }
+    }
+
+    /// `host` is the host of the remote, which selects the entry of a
+    /// `.netrc` file.
+    pub fn load(&self, host: Option<&str>) -> Result<Credentials, ConfigError> {
+        let content = std::fs::read_to_string(self.path())?;
+
+        let credentials = match self {
+            Self::Token { username, .. } => Credentials {
+                username: username.clone(),
+                password: content.trim_end_matches(['\r', '\n']).to_string(),
+            },
+            Self::File { .. } if !self.is_netrc() => {
+                let file: CredentialsFile = toml::from_str(&content)?;
+                Credentials {
+                    username: file.username,
+                    password: file.password,
+                }
+            }
+            Self::File { .. } => {
+                let netrc = Netrc::parse(&content).map_err(ConfigError::Netrc)?;
+                let entry = netrc
+                    .find(host)
+                    .ok_or_else(|| ConfigError::NetrcEntryMissing(host.map(str::to_string)))?;
+                match (&entry.login, &entry.password) {
+                    (Some(login), Some(password)) => Credentials {
+                        username: login.clone(),
+                        password: password.clone(),
+                    },
+                    _ => return Err(ConfigError::NetrcEntryMissing(host.map(str::to_string))),
+                }
+            }
+        };
+
+        if credentials.password.is_empty() {
+            return Err(ConfigError::EmptySecret);
+        }
+
+        Ok(credentials)
+    }
+}
+
+/// SSH remotes, e.g. `git@github.com:org/repo.git`, are fetched from by
+/// running `ssh` like Git does, so `~/.ssh/config` applies as usual. The
+/// worker cannot answer prompts, so keys must not need a passphrase unless
+/// an agent holds them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshConfig {
+    /// Run by the shell instead of `GIT_SSH_COMMAND`, or `ssh` if that is not
+    /// set, e.g. `ssh -p 2222`.
+    pub command: Option<String>,
+    /// The private key to authenticate with. No other key is offered.
+    pub identity_file: Option<PathBuf>,
+    /// Check host keys against this file instead of `~/.ssh/known_hosts`.
+    /// Hosts that are not in it are rejected.
+    pub known_hosts: Option<PathBuf>,
+}
+
+impl SshConfig {
+    /// The command that connects to SSH remotes, as `GIT_SSH_COMMAND`, given
+    /// the one inherited from the environment.
+    pub fn ssh_command(&self, inherited: Option<&str>) -> String {
+        let mut command = self
+            .command
+            .as_deref()
+            .or(inherited)
+            .unwrap_or("ssh")
+            .to_string();
+
+        command.push_str(" -o BatchMode=yes");
+        if let Some(identity_file) = &self.identity_file {
+            command.push_str(&format!(
+                " -i {} -o IdentitiesOnly=yes",
+                shell_quote(&identity_file.display().to_string())
+            ));
+        }
+        if let Some(known_hosts) = &self.known_hosts {
+            command.push_str(&format!(
+                " -o UserKnownHostsFile={} -o StrictHostKeyChecking=yes",
+                shell_quote(&known_hosts.display().to_string())
+            ));
+        }
+
+        command
+    }
+
+    /// Make gix, and Git, connect to SSH remotes with `ssh_command`.
+    ///
+    /// Must be called before any other threads are started, as it changes
+    /// the environment of the process.
+    pub fn apply(&self) {
+        let inherited = std::env::var("GIT_SSH_COMMAND").ok();
+        std::env::set_var("GIT_SSH_COMMAND", self.ssh_command(inherited.as_deref()));
+        // The options above are those of OpenSSH. Without this, gix treats a
+        // command with arguments as an unknown program, which cannot be told
+        // the port of the remote.
+        if std::env::var_os("GIT_SSH_VARIANT").is_none() {
+            std::env::set_var("GIT_SSH_VARIANT", "ssh");
+        }
+    }
+}
+
+/// Quote `value` as a single word for `/bin/sh`.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Notifications sent for every event in `events`. Failing to send one is
+/// logged, but does not affect the update.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    /// Defaults to every event.
+    #[serde(default = "NotifyEvent::all")]
+    pub events: Vec<NotifyEvent>,
+    /// URLs that the event is posted to as JSON.
+    ///
+    /// They often carry a token, so they are left out like `listen.secret`.
+    #[serde(default, skip_serializing)]
+    pub webhooks: Vec<String>,
+    /// Slack incoming webhook URLs, or those of a compatible service, that a
+    /// summary of the event is posted to.
+    ///
+    /// Anyone with such a URL can post to the channel, so they are left out
+    /// too.
+    #[serde(default, skip_serializing)]
+    pub slack: Vec<String>,
+    /// Run with the event as JSON on stdin.
+    pub command: Option<HookCommand>,
+    /// How many lines of the output of the last step to include. Defaults to
+    /// 20.
+    #[serde(default = "default_log_lines")]
+    pub log_lines: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyEvent {
+    /// An update has been found, and its hook or steps are about to run.
+    Started,
+    Succeeded,
+    Failed,
+    /// The worker has stopped, either because it was told to or because of
+    /// an error.
+    Stopped,
+}
+
+impl NotifyEvent {
+    fn all() -> Vec<Self> {
+        vec![Self::Started, Self::Succeeded, Self::Failed, Self::Stopped]
+    }
+}
+
+fn default_log_lines() -> usize {
+    20
+}
+
+/// Limits on the directories under `updates`. The directory of the latest
+/// successful update is always kept, whatever the limits.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Keep at most this many update directories.
+    pub keep_last: Option<usize>,
+    /// Delete update directories older than this, e.g. `30d`.
+    pub max_age: Option<Age>,
+    /// Delete the oldest update directories while all of them together are
+    /// larger than this, e.g. `1G`.
+    pub max_total_size: Option<Size>,
+}
+
+impl RetentionConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.keep_last.is_some() || self.max_age.is_some() || self.max_total_size.is_some()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpdateStrategy {
+    /// The `on_update` hook is expected to update the branch itself.
+    #[default]
+    Hook,
+    /// Outpost fast-forwards the branch and checks out the working tree
+    /// before running the hook. Diverged branches are not updated.
+    FastForward,
+    /// Like `FastForward`, but the branch is moved to the remote commit even
+    /// if the histories have diverged.
+    Reset,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FailureConfig {
+    /// What to do when fetching from the remote fails.
+    pub on_fetch_error: FetchFailurePolicy,
+    /// Seconds to wait before retrying a failed fetch. Doubled after every
+    /// consecutive failure.
+    pub initial_backoff: u64,
+    /// Upper limit for the delay between retries, in seconds.
+    pub max_backoff: u64,
+    /// What to do when a hook fails, or does not update the branch as
+    /// expected.
+    pub on_hook_error: HookFailurePolicy,
+    /// Stop the worker after this many consecutive failures, whatever the
+    /// policies above say.
+    pub max_consecutive_failures: Option<u32>,
+}
+
+impl Default for FailureConfig {
+    fn default() -> Self {
+        Self {
+            on_fetch_error: FetchFailurePolicy::Stop,
+            initial_backoff: 5,
+            max_backoff: 300,
+            on_hook_error: HookFailurePolicy::Stop,
+            max_consecutive_failures: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FetchFailurePolicy {
+    Stop,
+    /// Try again after an exponentially increasing delay.
+    Retry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookFailurePolicy {
+    Stop,
+    /// Keep checking for updates as usual.
+    Continue,
+    /// Stop checking for updates until the worker is reloaded.
+    Pause,
+}
+
+/// A hook, written as the path to an executable, as a program followed by
+/// its arguments, e.g. `["make", "deploy"]`, or as a script run by
+/// `shell_program`, e.g.
+/// `{ shell = "cargo build --release && systemctl restart app" }`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HookCommand {
+    Path(PathBuf),
+    Argv(Vec<String>),
+    Shell { shell: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepConfig {
+    /// Names the directory with the output of the step.
+    pub name: String,
+    /// Looked up in `PATH` if it is a bare name, e.g. `make`.
+    pub command: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Defaults to the directory of the worker.
+    pub working_dir: Option<PathBuf>,
+    /// Set in addition to the `OUTPOST_*` variables.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Seconds, instead of `hook_timeout`.
+    pub timeout: Option<u64>,
+    /// Run the remaining steps, and consider the update successful, even if
+    /// this step fails.
+    #[serde(default)]
+    pub continue_on_error: bool,
+}
+
+impl StepConfig {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BranchConfig {
+    /// Matched against the short name of the branch on the remote.
+    pub pattern: Pattern,
+    /// Run instead of the top-level `on_update` when a matching branch changes.
+    pub on_update: Option<HookCommand>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagConfig {
+    /// Only watch tags whose name matches this pattern.
+    pub pattern: Option<Pattern>,
+    /// Only watch tags whose name, without a leading `v`, is a version that
+    /// satisfies this requirement.
+    pub version: Option<VersionReq>,
+    /// Run instead of the top-level `on_update` when a new tag appears.
+    pub on_update: Option<HookCommand>,
+}
+
+impl TagConfig {
+    pub fn matches(&self, tag: &str) -> bool {
+        let pattern_matches = self.pattern.as_ref().map_or(true, |p| p.matches(tag));
+        let version_matches = self.version.as_ref().map_or(true, |requirement| {
+            let version = tag.strip_prefix('v').unwrap_or(tag);
+            Version::parse(version).map_or(false, |v| requirement.matches(&v))
+        });
+        pattern_matches && version_matches
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Credentials {
     pub username: String,
     pub password: String,
@@ -25,6 +480,36 @@ pub struct Credentials {
 pub enum ConfigError {
     Io(io::Error),
     Toml(toml::de::Error),
+    /// Neither `on_update` nor `steps` is given.
+    HookMissing,
+    /// Both `on_update` and `steps` are given.
+    HookConflict,
+    /// A hook is an empty list of arguments, or `shell_program` is empty.
+    EmptyCommand,
+    /// A hook, or the program that runs `shell` hooks, does not exist.
+    CommandNotFound(PathBuf),
+    /// A hook, or the program that runs `shell` hooks, is not executable.
+    CommandNotExecutable(PathBuf),
+    /// The webhook secret, which would accept any request if empty, or the
+    /// token or password is empty.
+    EmptySecret,
+    /// `branch` is given along with `branches` or `tags`.
+    BranchConflict,
+    /// `watch` is given along with settings that need a checkout.
+    WatchConflict,
+    /// `rollback` or an `update_strategy` other than `hook` is given along
+    /// with `branches` or `tags`. Only the branch that HEAD points to is
+    /// checked out.
+    CheckoutConflict,
+    /// The identity file or the known hosts file for SSH does not exist.
+    FileNotFound(PathBuf),
+    /// A file with secrets is not owned by the user, or can be accessed by
+    /// anyone else.
+    InsecurePermissions(PathBuf),
+    Netrc(NetrcError),
+    /// The `.netrc` file has no entry with both a login and a password for
+    /// the host of the remote, nor a `default` one.
+    NetrcEntryMissing(Option<String>),
 }
 
 impl From<toml::de::Error> for ConfigError {
@@ -43,7 +528,111 @@ impl Config {
     pub fn from_path(path: &Path) -> Result<Self, ConfigError> {
         let content = std::fs::read_to_string(path)?;
         let base = path.parent().unwrap_or(Path::new("."));
-        Self::from_toml(&content)?.with_absolute_paths(base)
+        Self::from_toml(&content)?
+            .with_absolute_paths(base)?
+            .validated()
+    }
+
+    /// Whether to follow a single remote branch, `branch` or else the one
+    /// tracked by the branch that HEAD points to, and update the checkout
+    /// for it. This is the case unless branches or tags to watch are
+    /// configured explicitly, or a remote repository is watched.
+    pub fn follows_head(&self) -> bool {
+        self.branches.is_empty() && self.tags.is_none() && self.watch.is_none()
+    }
+
+    /// Whether `trigger --force` has an update to run. Tags are never
+    /// forced, so it has none when only tags are watched.
+    pub fn supports_force(&self) -> bool {
+        self.watch.is_some() || !self.branches.is_empty() || self.follows_head()
+    }
+
+    pub fn hook_timeout(&self) -> Option<Duration> {
+        self.hook_timeout.map(Duration::from_secs)
+    }
+
+    /// The branch configuration whose pattern matches `branch` first.
+    pub fn branch(&self, branch: &str) -> Option<&BranchConfig> {
+        self.branches.iter().find(|b| b.pattern.matches(branch))
+    }
+
+    /// The program, and its arguments, that runs `shell` hooks.
+    pub fn shell_program(&self) -> Vec<&str> {
+        match &self.shell_program {
+            Some(shell) => shell.iter().map(String::as_str).collect(),
+            None => DEFAULT_SHELL.to_vec(),
+        }
+    }
+
+    fn validated(self) -> Result<Self, ConfigError> {
+        match (&self.on_update, self.steps.is_empty()) {
+            (None, true) => return Err(ConfigError::HookMissing),
+            (Some(_), false) => return Err(ConfigError::HookConflict),
+            _ => {}
+        }
+
+        let hooks = self
+            .on_update
+            .iter()
+            .chain(&self.on_failure)
+            .chain(self.branches.iter().filter_map(|b| b.on_update.as_ref()))
+            .chain(self.tags.iter().filter_map(|t| t.on_update.as_ref()))
+            .chain(self.notify.iter().filter_map(|n| n.command.as_ref()));
+
+        for hook in hooks {
+            let program = match hook {
+                HookCommand::Path(path) => path.as_path(),
+                HookCommand::Argv(argv) => argv.first().ok_or(ConfigError::EmptyCommand)?.as_ref(),
+                HookCommand::Shell { .. } => Path::new(
+                    *self
+                        .shell_program()
+                        .first()
+                        .ok_or(ConfigError::EmptyCommand)?,
+                ),
+            };
+            find_executable(program)?;
+        }
+
+        for step in &self.steps {
+            find_executable(&step.command)?;
+        }
+
+        if self.watch.is_some()
+            && (self.remote.is_some()
+                || self.branch.is_some()
+                || !self.branches.is_empty()
+                || self.tags.is_some()
+                || self.rollback
+                || self.update_strategy != UpdateStrategy::Hook)
+        {
+            return Err(ConfigError::WatchConflict);
+        }
+
+        if !self.follows_head() && (self.rollback || self.update_strategy != UpdateStrategy::Hook) {
+            return Err(ConfigError::CheckoutConflict);
+        }
+
+        if self.branch.is_some() && !self.follows_head() {
+            return Err(ConfigError::BranchConflict);
+        }
+
+        if self.listen.as_ref().map_or(false, |l| l.secret.is_empty()) {
+            return Err(ConfigError::EmptySecret);
+        }
+
+        if let Some(ssh) = &self.ssh {
+            for path in ssh.identity_file.iter().chain(&ssh.known_hosts) {
+                if !path.is_file() {
+                    return Err(ConfigError::FileNotFound(path.clone()));
+                }
+            }
+        }
+
+        if let Some(credentials) = &self.credentials {
+            check_secret_file(credentials.path())?;
+        }
+
+        Ok(self)
     }
 
     fn from_toml(content: &str) -> Result<Self, ConfigError> {
@@ -59,16 +648,124 @@ impl Config {
             .stderr
             .map(|path| normalize_path(base, path))
             .transpose()?;
-        let on_update = normalize_path(base, self.on_update)?;
+        let on_update = self
+            .on_update
+            .map(|hook| normalize_hook(base, hook))
+            .transpose()?;
+        let on_failure = self
+            .on_failure
+            .map(|hook| normalize_hook(base, hook))
+            .transpose()?;
+        let notify = self
+            .notify
+            .map(|notify| {
+                Ok::<_, io::Error>(NotifyConfig {
+                    command: notify
+                        .command
+                        .map(|hook| normalize_hook(base, hook))
+                        .transpose()?,
+                    ..notify
+                })
+            })
+            .transpose()?;
+        let ssh = self
+            .ssh
+            .map(|ssh| {
+                Ok::<_, io::Error>(SshConfig {
+                    identity_file: ssh
+                        .identity_file
+                        .map(|path| normalize_path(base, path))
+                        .transpose()?,
+                    known_hosts: ssh
+                        .known_hosts
+                        .map(|path| normalize_path(base, path))
+                        .transpose()?,
+                    ..ssh
+                })
+            })
+            .transpose()?;
+        let credentials = self
+            .credentials
+            .map(|credentials| {
+                Ok::<_, io::Error>(match credentials {
+                    CredentialsConfig::Token {
+                        username,
+                        token_file,
+                    } => CredentialsConfig::Token {
+                        username,
+                        token_file: normalize_path(base, token_file)?,
+                    },
+                    CredentialsConfig::File { file } => CredentialsConfig::File {
+                        file: normalize_path(base, file)?,
+                    },
+                })
+            })
+            .transpose()?;
+        let steps = self
+            .steps
+            .into_iter()
+            .map(|step| {
+                Ok::<_, io::Error>(StepConfig {
+                    command: normalize_command(base, step.command)?,
+                    working_dir: step
+                        .working_dir
+                        .map(|path| relative_to(base, path).canonicalize())
+                        .transpose()?,
+                    ..step
+                })
+            })
+            .collect::<Result<_, io::Error>>()?;
         let updates = normalize_path(base, self.updates)?;
+        let branches = self
+            .branches
+            .into_iter()
+            .map(|branch| {
+                Ok(BranchConfig {
+                    on_update: branch
+                        .on_update
+                        .map(|hook| normalize_hook(base, hook))
+                        .transpose()?,
+                    ..branch
+                })
+            })
+            .collect::<Result<_, io::Error>>()?;
+        let tags = self
+            .tags
+            .map(|tags| {
+                Ok::<_, io::Error>(TagConfig {
+                    on_update: tags
+                        .on_update
+                        .map(|hook| normalize_hook(base, hook))
+                        .transpose()?,
+                    ..tags
+                })
+            })
+            .transpose()?;
 
         Ok(Self {
             stdout,
             stderr,
             on_update,
+            steps,
+            on_failure,
+            rollback: self.rollback,
             updates,
+            shell_program: self.shell_program,
             iterations: self.iterations,
             interval: self.interval,
+            hook_timeout: self.hook_timeout,
+            remote: self.remote,
+            branch: self.branch,
+            branches,
+            watch: self.watch,
+            tags,
+            update_strategy: self.update_strategy,
+            failure: self.failure,
+            retention: self.retention,
+            listen: self.listen,
+            notify,
+            ssh,
+            credentials,
         })
     }
 }
@@ -84,6 +781,70 @@ fn normalize_path(base: &Path, path: PathBuf) -> Result<PathBuf, io::Error> {
     }
 }
 
+fn normalize_hook(base: &Path, hook: HookCommand) -> Result<HookCommand, io::Error> {
+    match hook {
+        HookCommand::Path(path) => normalize_path(base, path).map(HookCommand::Path),
+        HookCommand::Argv(mut argv) => {
+            if let Some(program) = argv.first_mut() {
+                let normalized = normalize_command(base, PathBuf::from(&*program))?;
+                *program = normalized.display().to_string();
+            }
+            Ok(HookCommand::Argv(argv))
+        }
+        HookCommand::Shell { shell } => Ok(HookCommand::Shell { shell }),
+    }
+}
+
+/// Check that `path` is owned by the user, and that nobody else can access
+/// it, like `ssh` does for private keys.
+fn check_secret_file(path: &Path) -> Result<(), ConfigError> {
+    let metadata = path.metadata()?;
+    // SAFETY: `geteuid` always succeeds.
+    let user = unsafe { libc::geteuid() };
+
+    if metadata.uid() != user || metadata.mode() & 0o077 != 0 {
+        return Err(ConfigError::InsecurePermissions(path.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+/// Look up `program` like `Command` does, and check that it is executable.
+fn find_executable(program: &Path) -> Result<(), ConfigError> {
+    let candidates = if program.components().count() == 1 && program.is_relative() {
+        std::env::var_os("PATH")
+            .map(|paths| {
+                std::env::split_paths(&paths)
+                    .map(|directory| directory.join(program))
+                    .collect()
+            })
+            .unwrap_or_default()
+    } else {
+        vec![program.to_path_buf()]
+    };
+
+    let path = candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| ConfigError::CommandNotFound(program.to_path_buf()))?;
+
+    if path.metadata()?.permissions().mode() & 0o111 == 0 {
+        return Err(ConfigError::CommandNotExecutable(path));
+    }
+
+    Ok(())
+}
+
+/// Like `normalize_path`, but bare names are left as they are, to be looked
+/// up in `PATH`.
+fn normalize_command(base: &Path, command: PathBuf) -> Result<PathBuf, io::Error> {
+    if command.components().count() == 1 && command.is_relative() {
+        Ok(command)
+    } else {
+        normalize_path(base, command)
+    }
+}
+
 fn relative_to(base: &Path, path: PathBuf) -> PathBuf {
     if path.is_relative() {
         let mut base = base.to_path_buf();
@@ -94,22 +855,170 @@ fn relative_to(base: &Path, path: PathBuf) -> PathBuf {
     }
 }
 
-#[derive(Debug)]
-pub enum CredentialsError {
-    MissingUsername,
-    MissingPassword,
-}
-
-impl Credentials {
-    pub fn from_env() -> Result<Option<Self>, CredentialsError> {
-        match (
-            std::env::var("GIT_USERNAME").ok(),
-            std::env::var("GIT_PASSWORD").ok(),
-        ) {
-            (None, None) => Ok(None),
-            (None, Some(_)) => Err(CredentialsError::MissingUsername),
-            (Some(_), None) => Err(CredentialsError::MissingPassword),
-            (Some(username), Some(password)) => Ok(Some(Self { username, password })),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssh_config(command: Option<&str>) -> SshConfig {
+        SshConfig {
+            command: command.map(str::to_string),
+            identity_file: None,
+            known_hosts: None,
+        }
+    }
+
+    #[test]
+    fn ssh_command_falls_back_to_env_then_ssh() {
+        let inherited = Some("ssh -F /etc/outpost/ssh_config");
+
+        assert_eq!(
+            ssh_config(Some("ssh -p 2222")).ssh_command(inherited),
+            "ssh -p 2222 -o BatchMode=yes"
+        );
+        assert_eq!(
+            ssh_config(None).ssh_command(inherited),
+            "ssh -F /etc/outpost/ssh_config -o BatchMode=yes"
+        );
+        assert_eq!(ssh_config(None).ssh_command(None), "ssh -o BatchMode=yes");
+    }
+
+    #[test]
+    fn ssh_command_adds_identity_and_known_hosts() {
+        let config = SshConfig {
+            identity_file: Some(PathBuf::from("/keys/deploy key")),
+            known_hosts: Some(PathBuf::from("/etc/outpost/known_hosts")),
+            ..ssh_config(Some("ssh"))
+        };
+
+        assert_eq!(
+            config.ssh_command(None),
+            "ssh -o BatchMode=yes -i '/keys/deploy key' -o IdentitiesOnly=yes \
+             -o UserKnownHostsFile='/etc/outpost/known_hosts' -o StrictHostKeyChecking=yes"
+        );
+    }
+
+    #[test]
+    fn force_is_not_supported_for_tags_only() {
+        let config = |extra: &str| -> Config {
+            toml::from_str(&format!("updates = \"updates\"\n{extra}")).unwrap()
+        };
+
+        assert!(config("").supports_force());
+        assert!(config("[[branches]]\npattern = \"release/*\"").supports_force());
+        assert!(
+            config("[watch]\nurl = \"https://example.com/repo.git\"\nref = \"main\"")
+                .supports_force()
+        );
+        assert!(!config("[tags]").supports_force());
+        assert!(config("[tags]\n[[branches]]\npattern = \"main\"").supports_force());
+    }
+
+    #[test]
+    fn checkout_settings_are_rejected_with_branches_or_tags() {
+        let validated = |extra: &str| {
+            toml::from_str::<Config>(&format!(
+                "updates = \"updates\"\non_update = \"/bin/sh\"\n{extra}"
+            ))
+            .unwrap()
+            .validated()
+        };
+
+        assert!(validated("rollback = true").is_ok());
+        assert!(validated("update_strategy = \"reset\"").is_ok());
+        assert!(matches!(
+            validated("rollback = true\n[tags]"),
+            Err(ConfigError::CheckoutConflict)
+        ));
+        assert!(matches!(
+            validated("update_strategy = \"fast-forward\"\n[[branches]]\npattern = \"*\""),
+            Err(ConfigError::CheckoutConflict)
+        ));
+        assert!(validated("[[branches]]\npattern = \"*\"").is_ok());
+    }
+
+    fn tag_config(pattern: Option<&str>, version: Option<&str>) -> TagConfig {
+        TagConfig {
+            pattern: pattern.map(Pattern::new),
+            version: version.map(|v| VersionReq::parse(v).unwrap()),
+            on_update: None,
         }
     }
+
+    #[test]
+    fn tags_match_everything_without_filters() {
+        let tags = tag_config(None, None);
+        assert!(tags.matches("v1.0.0"));
+        assert!(tags.matches("nightly"));
+    }
+
+    #[test]
+    fn tags_match_pattern() {
+        let tags = tag_config(Some("release-*"), None);
+        assert!(tags.matches("release-2023"));
+        assert!(!tags.matches("v1.0.0"));
+    }
+
+    #[test]
+    fn tags_match_version_with_or_without_v() {
+        let tags = tag_config(None, Some(">=1.2, <2"));
+        assert!(tags.matches("v1.2.0"));
+        assert!(tags.matches("1.9.9"));
+        assert!(!tags.matches("v1.1.9"));
+        assert!(!tags.matches("v2.0.0"));
+        // Pre-releases only match requirements that mention them.
+        assert!(!tags.matches("v1.5.0-rc.1"));
+    }
+
+    #[test]
+    fn tags_that_are_not_versions_do_not_match_version() {
+        let tags = tag_config(None, Some("^1"));
+        assert!(!tags.matches("latest"));
+        assert!(!tags.matches("v1"));
+        assert!(!tags.matches("vv1.0.0"));
+        assert!(!tags.matches(""));
+    }
+
+    #[test]
+    fn tags_must_match_both_pattern_and_version() {
+        let tags = tag_config(Some("v*"), Some("^1"));
+        assert!(tags.matches("v1.0.0"));
+        assert!(!tags.matches("1.0.0"));
+        assert!(!tags.matches("v2.0.0"));
+    }
+
+    #[test]
+    fn secrets_are_not_serialized() {
+        let config = Config::from_toml(
+            r#"
+            updates = "updates"
+            on_update = "deploy.sh"
+
+            [listen]
+            address = "127.0.0.1:8080"
+            secret = "webhook-secret"
+
+            [notify]
+            webhooks = ["https://example.com/hook?token=webhook-token"]
+            slack = ["https://hooks.slack.com/services/secret"]
+            "#,
+        )
+        .unwrap();
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains("webhook-secret"), "{json}");
+        assert!(!json.contains("webhook-token"), "{json}");
+        assert!(!json.contains("hooks.slack.com"), "{json}");
+
+        let stored: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(stored.listen.unwrap().secret, "");
+        let notify = stored.notify.unwrap();
+        assert!(notify.webhooks.is_empty());
+        assert!(notify.slack.is_empty());
+    }
+
+    #[test]
+    fn shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
 }
@@ -0,0 +1,113 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// How long the CLI waits for a worker to answer. Requests are answered right
+/// away, even while an update is running.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the CLI asks of a worker over its control socket.
+///
+/// Every connection carries a single request, written as one line of JSON,
+/// which the worker answers with a single line of JSON.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    /// Check for updates right away. If `force` is set, the update is run
+    /// even if nothing has changed.
+    Trigger {
+        #[serde(default)]
+        force: bool,
+    },
+    /// Stop checking for updates until `Resume`.
+    Pause,
+    Resume,
+    Shutdown,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+    Status(WorkerStatus),
+    Ok,
+    Error { message: String },
+}
+
+/// The live state of a worker, as opposed to what it has recorded in the
+/// database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerStatus {
+    pub directory: String,
+    pub process_id: u32,
+    pub state: WorkerState,
+    /// When the last check started.
+    pub last_check: Option<i64>,
+    /// When the next check is due, unless the worker is woken up before.
+    pub next_check: Option<i64>,
+    pub consecutive_failures: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerState {
+    /// Waiting for the next check.
+    Idle,
+    /// Checking for updates, or running an update.
+    Checking,
+    /// Paused, either on request or after a failure.
+    Paused,
+}
+
+#[derive(Debug)]
+pub enum ControlError {
+    HomeDirectoryMissing,
+    /// No worker is listening on the socket of the directory.
+    Connect(io::Error),
+    Io(io::Error),
+    InvalidResponse(serde_json::Error),
+    /// The worker refused the request.
+    Rejected(String),
+    UnexpectedResponse(Response),
+}
+
+/// The control socket of the worker in `directory`.
+///
+/// Named after a hash of the directory, since socket paths are limited to
+/// about a hundred bytes.
+pub fn socket_path(directory: &str) -> Option<PathBuf> {
+    let hash = Sha256::digest(directory.as_bytes());
+    let name = format!("{}.sock", hex::encode(&hash[..16]));
+
+    home::home_dir().map(|home| home.join(".outpost").join("sockets").join(name))
+}
+
+/// Send `request` to the worker in `directory`, and wait for its response.
+pub fn send(directory: &str, request: Request) -> Result<Response, ControlError> {
+    let path = socket_path(directory).ok_or(ControlError::HomeDirectoryMissing)?;
+
+    let mut stream = UnixStream::connect(path).map_err(ControlError::Connect)?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(ControlError::Io)?;
+
+    let mut line = serde_json::to_vec(&request).expect("failed to serialize request");
+    line.push(b'\n');
+    stream.write_all(&line).map_err(ControlError::Io)?;
+
+    let mut response = String::new();
+    BufReader::new(stream)
+        .read_line(&mut response)
+        .map_err(ControlError::Io)?;
+
+    match serde_json::from_str(&response).map_err(ControlError::InvalidResponse)? {
+        Response::Error { message } => Err(ControlError::Rejected(message)),
+        response => Ok(response),
+    }
+}
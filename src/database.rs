@@ -1,4 +1,18 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::Infallible,
+    fmt, io,
+    path::Path,
+    time::Duration,
+};
+
 use serde::{Deserialize, Serialize};
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Transactional,
+};
+
+use crate::system::{is_process_running, process_identity, ProcessIdentity};
 
 #[derive(Serialize, Deserialize)]
 pub struct Key(Vec<u8>);
@@ -22,15 +36,142 @@ impl From<Key> for String {
 }
 
 pub const PROCESSES: &str = "processes";
+pub const STATUSES: &str = "statuses";
+/// Every update run by any worker, keyed by `update_key`.
+pub const UPDATES: &str = "updates";
+
+const OPEN_ATTEMPTS: usize = 50;
+const OPEN_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Open the database at `directory`.
+///
+/// sled keeps an exclusive lock on the database while it is open, and both
+/// the CLI and the workers open it for short periods of time, so opening it
+/// is retried for a while if the lock is held by another process.
+pub fn open(directory: &Path) -> sled::Result<sled::Db> {
+    let mut attempts = 1;
+    loop {
+        match sled::open(directory) {
+            Err(error) if is_locked(&error) && attempts < OPEN_ATTEMPTS => {
+                attempts += 1;
+                std::thread::sleep(OPEN_RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Like [`open`], but waits without blocking the thread, for workers.
+pub async fn open_async(directory: &Path) -> sled::Result<sled::Db> {
+    let mut attempts = 1;
+    loop {
+        match sled::open(directory) {
+            Err(error) if is_locked(&error) && attempts < OPEN_ATTEMPTS => {
+                attempts += 1;
+                tokio::time::sleep(OPEN_RETRY_DELAY).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether opening the database failed because another process holds it.
+fn is_locked(error: &sled::Error) -> bool {
+    matches!(error, sled::Error::Io(error) if error.kind() == io::ErrorKind::Other)
+}
+
+/// Remove the entry of the worker with `key` along with its status, so that
+/// a worker started later with the same key starts afresh.
+///
+/// Returns whether there was an entry.
+pub fn remove_process(db: &sled::Db, key: &[u8]) -> sled::Result<bool> {
+    let processes = db.open_tree(PROCESSES)?;
+    let statuses = db.open_tree(STATUSES)?;
+
+    (&processes, &statuses)
+        .transaction(|(processes, statuses)| {
+            statuses.remove(key)?;
+            Ok::<_, ConflictableTransactionError<Infallible>>(processes.remove(key)?.is_some())
+        })
+        .map_err(|error| match error {
+            TransactionError::Abort(never) => match never {},
+            TransactionError::Storage(error) => error,
+        })
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Process {
     V1(v1::Process),
+    V2(Box<v2::Process>),
+}
+
+/// What a worker reports about itself, stored separately from `Process` so
+/// that the worker and the CLI do not overwrite each other's changes.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Status {
+    V1(v1::Status),
+}
+
+/// A single run of a hook, recorded when it starts and completed when it
+/// finishes.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Update {
+    V1(v1::Update),
+}
+
+/// The key of the update with the given ID in the `UPDATES` tree.
+///
+/// IDs are generated by sled in increasing order, and big-endian keys keep
+/// the tree sorted by ID.
+pub fn update_key(id: u64) -> [u8; 8] {
+    id.to_be_bytes()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpdateResult {
+    Succeeded,
+    Failed,
+}
+
+impl fmt::Display for UpdateResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Succeeded => f.write_str("succeeded"),
+            Self::Failed => f.write_str("failed"),
+        }
+    }
+}
+
+/// Recorded by a worker while its checks keep failing, and cleared after the
+/// next successful check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureState {
+    pub consecutive_failures: u32,
+    /// Unix timestamp (in seconds) of the last failure.
+    pub last_failure: i64,
+    pub last_error: String,
+    /// Whether the worker has stopped checking until it is reloaded.
+    pub paused: bool,
+}
+
+/// The object a reference on a remote repository pointed to when a worker
+/// without a checkout last listed it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeenRef {
+    pub url: String,
+    /// The full name of the reference, e.g. `refs/heads/main`.
+    pub reference: String,
+    pub id: String,
 }
 
 pub mod v1 {
+    use std::collections::{BTreeMap, BTreeSet};
+
     use serde::{Deserialize, Serialize};
 
+    use super::{FailureState, SeenRef, UpdateResult};
+
     #[derive(Debug, Serialize, Deserialize)]
     pub struct Process {
         pub directory: String,
@@ -38,13 +179,236 @@ pub mod v1 {
         pub stderr: String,
         pub process_id: Option<u32>,
     }
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    pub struct Status {
+        /// Unix timestamp (in seconds) of when the last update started.
+        pub last_update: Option<i64>,
+        pub last_result: Option<UpdateResult>,
+        /// The update directory of the last successful update.
+        pub last_successful_update: Option<String>,
+        /// The commit of the last successful update of every branch or tag, by
+        /// `refs/heads/<branch>` or `refs/tags/<tag>`, or `HEAD` for other
+        /// references.
+        #[serde(default)]
+        pub good_commits: BTreeMap<String, String>,
+        /// The last seen object ID of every watched remote branch.
+        ///
+        /// `None` until the worker has seen the remote for the first time.
+        pub seen_branches: Option<BTreeMap<String, String>>,
+        /// Every watched tag seen on the remote.
+        ///
+        /// `None` until the worker has seen the remote for the first time.
+        pub seen_tags: Option<BTreeSet<String>>,
+        /// The watched reference of a remote repository without a checkout.
+        ///
+        /// `None` until the worker has seen the remote for the first time.
+        pub seen_ref: Option<SeenRef>,
+        /// `None` unless the last check has failed.
+        pub failure: Option<FailureState>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Update {
+        /// The key of the worker that ran the update.
+        pub directory: String,
+        pub old_commit: Option<String>,
+        pub new_commit: String,
+        pub branch: Option<String>,
+        pub tag: Option<String>,
+        /// Unix timestamp (in seconds) of when the first step was started.
+        pub started_at: i64,
+        /// `None` while the update is running.
+        pub finished_at: Option<i64>,
+        /// The exit code of the last step that was run.
+        pub exit_code: Option<i32>,
+        /// `None` while the update is running.
+        pub result: Option<UpdateResult>,
+        pub update_dir: String,
+        /// The steps that have been started, in order.
+        pub steps: Vec<Step>,
+        /// Whether the update was forced with `outpost trigger --force`,
+        /// rather than found by the worker.
+        #[serde(default)]
+        pub manual: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Step {
+        pub name: String,
+        /// Unix timestamp (in seconds) of when the step was started.
+        pub started_at: i64,
+        /// `None` while the step is running.
+        pub finished_at: Option<i64>,
+        /// `None` while the step is running, or if it was killed by a signal.
+        pub exit_code: Option<i32>,
+        /// `None` while the step is running.
+        pub result: Option<UpdateResult>,
+        pub stdout: String,
+        pub stderr: String,
+    }
+}
+
+pub mod v2 {
+    use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+
+    use crate::{
+        config::{Config, HookCommand},
+        system::ProcessIdentity,
+    };
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Process {
+        pub directory: String,
+        pub stdout: String,
+        pub stderr: String,
+        pub process_id: Option<u32>,
+        /// The configuration file the worker was started with.
+        ///
+        /// Missing for entries migrated from `V1`.
+        pub config_path: Option<String>,
+        /// The configuration as it was resolved when the worker was started.
+        ///
+        /// Missing for entries migrated from `V1`, and for entries whose
+        /// configuration can no longer be read.
+        #[serde(default, deserialize_with = "lenient")]
+        pub config: Option<ProcessConfig>,
+        /// Unix timestamp (in seconds) of when the worker was started.
+        pub started_at: Option<i64>,
+        /// The version of the worker binary.
+        pub version: Option<String>,
+        /// Recorded right after the worker was spawned, to detect if
+        /// `process_id` has since been reused by an unrelated process.
+        pub identity: Option<ProcessIdentity>,
+    }
+
+    /// What a worker was started to do, as shown by the CLI.
+    ///
+    /// Kept apart from `Config`, so that changes to the configuration format
+    /// do not affect stored entries, and so that no secrets are stored.
+    /// Hooks are reduced to their program, as their arguments may hold
+    /// secrets too.
+    #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct ProcessConfig {
+        /// The program of `on_update`, if it is given instead of `steps`.
+        pub on_update: Option<String>,
+        /// The names of the steps.
+        pub steps: Vec<String>,
+        pub updates: String,
+        /// Seconds between checks.
+        pub interval: Option<u64>,
+        pub remote: Option<String>,
+        pub branch: Option<String>,
+        /// The patterns of the watched branches.
+        pub branches: Vec<String>,
+        pub tags: bool,
+        /// The reference watched on a remote repository without a checkout.
+        pub watch: Option<String>,
+        /// The address that webhooks are received on.
+        pub listen: Option<String>,
+    }
+
+    impl From<&Config> for ProcessConfig {
+        fn from(config: &Config) -> Self {
+            Self {
+                on_update: config.on_update.as_ref().map(hook_program),
+                steps: config.steps.iter().map(|step| step.name.clone()).collect(),
+                updates: config.updates.display().to_string(),
+                interval: config.interval,
+                remote: config.remote.clone(),
+                branch: config.branch.clone(),
+                branches: config
+                    .branches
+                    .iter()
+                    .map(|branch| branch.pattern.to_string())
+                    .collect(),
+                tags: config.tags.is_some(),
+                watch: config.watch.as_ref().map(|watch| watch.full_name()),
+                listen: config
+                    .listen
+                    .as_ref()
+                    .map(|listen| listen.address.to_string()),
+            }
+        }
+    }
+
+    fn hook_program(hook: &HookCommand) -> String {
+        match hook {
+            HookCommand::Path(path) => path.display().to_string(),
+            HookCommand::Argv(argv) => argv.first().cloned().unwrap_or_default(),
+            HookCommand::Shell { .. } => String::from("shell"),
+        }
+    }
+
+    /// `None` instead of an error if the stored value cannot be read, e.g.
+    /// because it was written by another version.
+    fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: DeserializeOwned,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Ok(serde_json::from_value(value).ok())
+    }
+
+    impl From<super::v1::Process> for Process {
+        fn from(value: super::v1::Process) -> Self {
+            Self {
+                directory: value.directory,
+                stdout: value.stdout,
+                stderr: value.stderr,
+                process_id: value.process_id,
+                config_path: None,
+                config: None,
+                started_at: None,
+                version: None,
+                identity: None,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProcessState {
+    /// The worker process is running.
+    Running,
+    /// The worker process was stopped through `outpost stop`.
+    Stopped,
+    /// The worker process has exited without being stopped, or its ID now
+    /// belongs to an unrelated process.
+    Dead,
+}
+
+impl fmt::Display for ProcessState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => f.write_str("running"),
+            Self::Stopped => f.write_str("stopped"),
+            Self::Dead => f.write_str("dead"),
+        }
+    }
 }
 
 impl Process {
+    /// Deserialize an entry, migrating it to the latest version.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(bytes).map(Self::migrate)
+    }
+
+    fn migrate(self) -> Self {
+        match self {
+            Process::V1(v) => Process::V2(Box::new(v.into())),
+            Process::V2(v) => Process::V2(v),
+        }
+    }
+
     #[allow(unused)]
     pub fn directory(&self) -> &str {
         match self {
             Process::V1(v) => &v.directory,
+            Process::V2(v) => &v.directory,
         }
     }
 
@@ -52,6 +416,7 @@ impl Process {
     pub fn stdout(&self) -> &str {
         match self {
             Process::V1(v) => &v.stdout,
+            Process::V2(v) => &v.stdout,
         }
     }
 
@@ -59,6 +424,7 @@ impl Process {
     pub fn stderr(&self) -> &str {
         match self {
             Process::V1(v) => &v.stderr,
+            Process::V2(v) => &v.stderr,
         }
     }
 
@@ -66,6 +432,7 @@ impl Process {
     pub fn active(&self) -> bool {
         match self {
             Process::V1(v) => v.process_id.is_some(),
+            Process::V2(v) => v.process_id.is_some(),
         }
     }
 
@@ -73,6 +440,345 @@ impl Process {
     pub fn process_id(&self) -> Option<u32> {
         match self {
             Process::V1(v) => v.process_id,
+            Process::V2(v) => v.process_id,
+        }
+    }
+
+    pub fn config_path(&self) -> Option<&str> {
+        match self {
+            Process::V1(_) => None,
+            Process::V2(v) => v.config_path.as_deref(),
+        }
+    }
+
+    pub fn config(&self) -> Option<&v2::ProcessConfig> {
+        match self {
+            Process::V1(_) => None,
+            Process::V2(v) => v.config.as_ref(),
         }
     }
+
+    pub fn started_at(&self) -> Option<i64> {
+        match self {
+            Process::V1(_) => None,
+            Process::V2(v) => v.started_at,
+        }
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            Process::V1(_) => None,
+            Process::V2(v) => v.version.as_deref(),
+        }
+    }
+
+    pub fn identity(&self) -> Option<&ProcessIdentity> {
+        match self {
+            Process::V1(_) => None,
+            Process::V2(v) => v.identity.as_ref(),
+        }
+    }
+
+    /// Check the recorded worker process against the running processes.
+    pub fn state(&self) -> ProcessState {
+        let Some(id) = self.process_id() else {
+            return ProcessState::Stopped;
+        };
+
+        if !is_process_running(id) {
+            return ProcessState::Dead;
+        }
+
+        match (self.identity(), process_identity(id)) {
+            (Some(recorded), Some(current)) if *recorded != current => ProcessState::Dead,
+            _ => ProcessState::Running,
+        }
+    }
+
+    pub fn clear_process_id(&mut self) {
+        match self {
+            Process::V1(v) => v.process_id = None,
+            Process::V2(v) => {
+                v.process_id = None;
+                v.identity = None;
+            }
+        }
+    }
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self::V1(v1::Status::default())
+    }
+}
+
+impl Status {
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(bytes)
+    }
+
+    pub fn last_update(&self) -> Option<i64> {
+        match self {
+            Status::V1(v) => v.last_update,
+        }
+    }
+
+    pub fn last_result(&self) -> Option<UpdateResult> {
+        match self {
+            Status::V1(v) => v.last_result,
+        }
+    }
+
+    pub fn set_last_update(&mut self, timestamp: i64, result: UpdateResult) {
+        match self {
+            Status::V1(v) => {
+                v.last_update = Some(timestamp);
+                v.last_result = Some(result);
+            }
+        }
+    }
+
+    pub fn last_successful_update(&self) -> Option<&str> {
+        match self {
+            Status::V1(v) => v.last_successful_update.as_deref(),
+        }
+    }
+
+    pub fn set_last_successful_update(
+        &mut self,
+        update_dir: String,
+        reference: String,
+        commit: String,
+    ) {
+        match self {
+            Status::V1(v) => {
+                v.last_successful_update = Some(update_dir);
+                v.good_commits.insert(reference, commit);
+            }
+        }
+    }
+
+    /// The commit of the last successful update of `reference`.
+    pub fn good_commit(&self, reference: &str) -> Option<&str> {
+        match self {
+            Status::V1(v) => v.good_commits.get(reference).map(String::as_str),
+        }
+    }
+
+    pub fn seen_branches(&self) -> Option<&BTreeMap<String, String>> {
+        match self {
+            Status::V1(v) => v.seen_branches.as_ref(),
+        }
+    }
+
+    pub fn set_seen_branches(&mut self, branches: BTreeMap<String, String>) {
+        match self {
+            Status::V1(v) => v.seen_branches = Some(branches),
+        }
+    }
+
+    pub fn seen_tags(&self) -> Option<&BTreeSet<String>> {
+        match self {
+            Status::V1(v) => v.seen_tags.as_ref(),
+        }
+    }
+
+    pub fn set_seen_tags(&mut self, tags: BTreeSet<String>) {
+        match self {
+            Status::V1(v) => v.seen_tags = Some(tags),
+        }
+    }
+
+    pub fn seen_ref(&self) -> Option<&SeenRef> {
+        match self {
+            Status::V1(v) => v.seen_ref.as_ref(),
+        }
+    }
+
+    pub fn set_seen_ref(&mut self, seen_ref: SeenRef) {
+        match self {
+            Status::V1(v) => v.seen_ref = Some(seen_ref),
+        }
+    }
+
+    pub fn failure(&self) -> Option<&FailureState> {
+        match self {
+            Status::V1(v) => v.failure.as_ref(),
+        }
+    }
+
+    pub fn set_failure(&mut self, failure: Option<FailureState>) {
+        match self {
+            Status::V1(v) => v.failure = failure,
+        }
+    }
+}
+
+impl Update {
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(bytes)
+    }
+
+    pub fn directory(&self) -> &str {
+        match self {
+            Update::V1(v) => &v.directory,
+        }
+    }
+
+    pub fn old_commit(&self) -> Option<&str> {
+        match self {
+            Update::V1(v) => v.old_commit.as_deref(),
+        }
+    }
+
+    pub fn new_commit(&self) -> &str {
+        match self {
+            Update::V1(v) => &v.new_commit,
+        }
+    }
+
+    pub fn branch(&self) -> Option<&str> {
+        match self {
+            Update::V1(v) => v.branch.as_deref(),
+        }
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            Update::V1(v) => v.tag.as_deref(),
+        }
+    }
+
+    pub fn started_at(&self) -> i64 {
+        match self {
+            Update::V1(v) => v.started_at,
+        }
+    }
+
+    pub fn finished_at(&self) -> Option<i64> {
+        match self {
+            Update::V1(v) => v.finished_at,
+        }
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            Update::V1(v) => v.exit_code,
+        }
+    }
+
+    pub fn result(&self) -> Option<UpdateResult> {
+        match self {
+            Update::V1(v) => v.result,
+        }
+    }
+
+    pub fn manual(&self) -> bool {
+        match self {
+            Update::V1(v) => v.manual,
+        }
+    }
+
+    pub fn steps(&self) -> &[v1::Step] {
+        match self {
+            Update::V1(v) => &v.steps,
+        }
+    }
+
+    pub fn start_step(&mut self, step: v1::Step) {
+        match self {
+            Update::V1(v) => v.steps.push(step),
+        }
+    }
+
+    /// Record the outcome of the step that was started last.
+    pub fn finish_step(&mut self, finished_at: i64, exit_code: Option<i32>, result: UpdateResult) {
+        match self {
+            Update::V1(v) => {
+                if let Some(step) = v.steps.last_mut() {
+                    step.finished_at = Some(finished_at);
+                    step.exit_code = exit_code;
+                    step.result = Some(result);
+                }
+            }
+        }
+    }
+
+    pub fn finish(&mut self, finished_at: i64, exit_code: Option<i32>, result: UpdateResult) {
+        match self {
+            Update::V1(v) => {
+                v.finished_at = Some(finished_at);
+                v.exit_code = exit_code;
+                v.result = Some(result);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_stored_configurations_are_ignored() {
+        let entry = |config: &str| {
+            format!(
+                r#"{{"V2": {{"directory": "/srv/app", "stdout": "out", "stderr": "err",
+                "process_id": 42, "config_path": "/srv/app/outpost.toml",
+                "config": {config}, "started_at": 1, "version": "0.1.0", "identity": null}}}}"#
+            )
+        };
+
+        let current = Process::from_slice(
+            entry(r#"{"on_update": "deploy.sh", "updates": "updates", "interval": 60}"#).as_bytes(),
+        )
+        .unwrap();
+        let config = current.config().unwrap();
+        assert_eq!(config.on_update.as_deref(), Some("deploy.sh"));
+        assert_eq!(config.interval, Some(60));
+
+        // E.g. the whole configuration, as stored by earlier versions.
+        let other = Process::from_slice(
+            entry(r#"{"on_update": ["make", "deploy"], "updates": "updates"}"#).as_bytes(),
+        )
+        .unwrap();
+        assert!(other.config().is_none());
+        assert_eq!(other.process_id(), Some(42));
+    }
+
+    #[test]
+    fn good_commits_are_recorded_per_reference() {
+        let mut status = Status::default();
+        status.set_last_successful_update("a".into(), "refs/heads/main".into(), "1".into());
+        status.set_last_successful_update("b".into(), "refs/tags/v1".into(), "2".into());
+
+        assert_eq!(status.good_commit("refs/heads/main"), Some("1"));
+        assert_eq!(status.good_commit("refs/tags/v1"), Some("2"));
+        assert_eq!(status.good_commit("refs/heads/dev"), None);
+        assert_eq!(status.last_successful_update(), Some("b"));
+    }
+
+    #[test]
+    fn removing_a_process_removes_its_status() {
+        let directory = std::env::temp_dir().join(format!("outpost-db-{}", std::process::id()));
+        let db = sled::Config::new()
+            .path(&directory)
+            .temporary(true)
+            .open()
+            .unwrap();
+        let processes = db.open_tree(PROCESSES).unwrap();
+        let statuses = db.open_tree(STATUSES).unwrap();
+
+        for key in ["/srv/a", "/srv/b"] {
+            processes.insert(key, "process").unwrap();
+            statuses.insert(key, "status").unwrap();
+        }
+
+        assert!(remove_process(&db, b"/srv/a").unwrap());
+        assert!(!processes.contains_key("/srv/a").unwrap());
+        assert!(!statuses.contains_key("/srv/a").unwrap());
+        assert!(statuses.contains_key("/srv/b").unwrap());
+
+        assert!(!remove_process(&db, b"/srv/a").unwrap());
+    }
 }
@@ -1,8 +1,11 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use crate::{
-    config::Credentials,
+    config::{Credentials, TagConfig},
     git::{Branch, GitError, Repository},
+    pattern::Pattern,
 };
-use gix::{protocol::handshake::Ref, ObjectId};
+use gix::{bstr::ByteSlice, protocol::handshake::Ref, ObjectId};
 
 #[derive(Debug)]
 pub enum FetchResult {
@@ -10,6 +13,25 @@ pub enum FetchResult {
     OutOfDate { remote_commit_id: ObjectId },
 }
 
+/// A remote branch whose tip differs from the last one seen.
+#[derive(Debug)]
+pub struct BranchUpdate {
+    /// The short name of the branch, e.g. `release/1.0`.
+    pub name: String,
+    /// `None` if the branch has not been seen before.
+    pub previous_id: Option<ObjectId>,
+    pub id: ObjectId,
+}
+
+/// A tag on the remote that has not been seen before.
+#[derive(Debug)]
+pub struct TagUpdate {
+    /// The short name of the tag, e.g. `v1.2.3`.
+    pub name: String,
+    /// The commit the tag points to, after peeling annotated tags.
+    pub commit_id: ObjectId,
+}
+
 #[derive(Debug)]
 pub enum FetchError {
     GitError(GitError),
@@ -22,13 +44,22 @@ impl From<GitError> for FetchError {
     }
 }
 
+/// Compare the tip of `branch` on the remote with `current_id`.
+///
+/// Unless `dry_run` is set, the objects are received and the remote-tracking
+/// references are updated as well.
 pub async fn fetch_and_compare(
     repository: &Repository,
     branch: &Branch,
     current_id: ObjectId,
+    dry_run: bool,
     credentials: Option<&Credentials>,
 ) -> Result<FetchResult, FetchError> {
-    let res = repository.fetch(credentials)?;
+    let res = if dry_run {
+        repository.fetch(credentials)?
+    } else {
+        repository.fetch_and_update(credentials)?
+    };
 
     let full_ref_name_on_remote = branch.as_reference().local().full_name();
 
@@ -59,3 +90,80 @@ pub async fn fetch_and_compare(
         })
     }
 }
+
+/// Fetch once and report every remote branch matching one of `patterns`
+/// whose tip is not the one recorded in `seen`.
+pub async fn fetch_and_compare_branches(
+    repository: &Repository,
+    patterns: &[Pattern],
+    seen: &BTreeMap<String, ObjectId>,
+    credentials: Option<&Credentials>,
+) -> Result<Vec<BranchUpdate>, FetchError> {
+    let res = repository.fetch(credentials)?;
+
+    let updates = res
+        .ref_map
+        .remote_refs
+        .iter()
+        .filter_map(|r| match r {
+            Ref::Direct {
+                full_ref_name,
+                object,
+            } => {
+                let name = full_ref_name.to_str().ok()?.strip_prefix("refs/heads/")?;
+                Some((name, *object))
+            }
+            Ref::Peeled { .. } | Ref::Symbolic { .. } | Ref::Unborn { .. } => None,
+        })
+        .filter(|(name, _)| patterns.iter().any(|p| p.matches(name)))
+        .filter_map(|(name, id)| {
+            let previous_id = seen.get(name).copied();
+            (previous_id != Some(id)).then(|| BranchUpdate {
+                name: name.to_string(),
+                previous_id,
+                id,
+            })
+        })
+        .collect();
+
+    Ok(updates)
+}
+
+/// Fetch once and report every tag matching `tags` that is not in `seen`.
+pub async fn fetch_and_compare_tags(
+    repository: &Repository,
+    tags: &TagConfig,
+    seen: &BTreeSet<String>,
+    credentials: Option<&Credentials>,
+) -> Result<Vec<TagUpdate>, FetchError> {
+    let res = repository.fetch_all_refs(credentials)?;
+
+    let updates = res
+        .ref_map
+        .remote_refs
+        .iter()
+        .filter_map(|r| match r {
+            // Annotated tags are peeled to the commit they point to.
+            Ref::Direct {
+                full_ref_name,
+                object,
+            }
+            | Ref::Peeled {
+                full_ref_name,
+                object,
+                ..
+            } => {
+                let name = full_ref_name.to_str().ok()?.strip_prefix("refs/tags/")?;
+                Some((name, *object))
+            }
+            Ref::Symbolic { .. } | Ref::Unborn { .. } => None,
+        })
+        .filter(|(name, _)| tags.matches(name) && !seen.contains(*name))
+        .map(|(name, commit_id)| TagUpdate {
+            name: name.to_string(),
+            commit_id,
+        })
+        .collect();
+
+    Ok(updates)
+}
@@ -1,15 +1,30 @@
 #![allow(clippy::result_large_err)]
 
+use std::{
+    collections::HashSet,
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
 use gix::{
+    bstr::ByteSlice,
     credentials::{helper::Action, protocol},
-    discover, reference,
+    discover, index,
+    object::{self, tree::diff},
+    objs::tree::EntryMode,
+    prelude::FindExt,
+    protocol::handshake::Ref,
+    reference,
+    refs::{transaction::PreviousValue, Target},
     remote::{
-        connect,
+        self, connect,
         fetch::{self, prepare, Outcome},
-        Direction,
+        ref_map, Direction,
     },
+    revision,
     sec::identity::Account,
-    Head, ObjectId, Remote,
+    traverse, Head, ObjectId, Remote,
 };
 
 use crate::config::Credentials;
@@ -25,12 +40,37 @@ pub enum GitError {
     RepositoryRemoteInvalid,
     RepositoryDefaultRemoteNotConfigured,
     RepositoryDefaultRemoteMissing,
+    /// The remote set in the configuration does not exist.
+    RepositoryRemoteMissing(String),
+    RemoteInit(remote::init::Error),
+    ScratchInit(gix::init::Error),
+    ScratchOpen(gix::open::Error),
     FetchConnect(connect::Error),
     FetchHandshake(prepare::Error),
     FetchReceive(fetch::Error),
+    RevisionWalk(revision::walk::Error),
+    RevisionWalkTraverse(traverse::commit::ancestors::Error),
+    /// A recorded commit ID, e.g. the last good commit, is not a valid ID.
+    InvalidCommitId(String),
+    ObjectMissing(object::find::existing::Error),
+    ObjectNotACommit(object::try_into::Error),
+    CommitTree(object::commit::Error),
+    TreeDiff(diff::for_each::Error),
+    ReferenceMissing(reference::find::existing::Error),
+    ReferenceEdit(reference::edit::Error),
+    /// The reference no longer points to the commit it was checked out from.
+    ReferenceMoved(String),
+    WorkTreeMissing,
+    WorkTree(io::Error),
+    IndexFromTree(traverse::tree::breadthfirst::Error),
+    IndexWrite(index::write::Error),
 }
 
-pub struct Repository(gix::Repository);
+pub struct Repository {
+    repo: gix::Repository,
+    /// The remote to fetch from, instead of the default one.
+    remote_name: Option<String>,
+}
 
 #[derive(Debug, Clone)]
 pub enum Branch {
@@ -83,19 +123,36 @@ impl<'a> Reference<'a> {
 impl Repository {
     pub fn discover() -> Result<Self, GitError> {
         gix::discover(".")
-            .map(Self)
+            .map(|repo| Self {
+                repo,
+                remote_name: None,
+            })
             .map_err(GitError::RepositoryNotFound)
     }
 
+    /// Fetch from the remote called `name`, if given, instead of the default
+    /// remote.
+    pub fn with_remote(self, name: Option<String>) -> Self {
+        Self {
+            remote_name: name,
+            ..self
+        }
+    }
+
     fn head(&self) -> Result<Head, GitError> {
-        self.0.head().map_err(GitError::RepositoryHeadMissing)
+        self.repo.head().map_err(GitError::RepositoryHeadMissing)
     }
 
     pub fn current_branch(&self) -> Result<Branch, GitError> {
-        self.head()?
+        self.head_branch()?.ok_or(GitError::RepositoryHeadDetached)
+    }
+
+    /// The branch that HEAD points to, or `None` if HEAD is detached.
+    pub fn head_branch(&self) -> Result<Option<Branch>, GitError> {
+        Ok(self
+            .head()?
             .referent_name()
-            .map(|r| Branch::Local(r.shorten().to_string()))
-            .ok_or(GitError::RepositoryHeadDetached)
+            .map(|r| Branch::Local(r.shorten().to_string())))
     }
 
     pub fn current_commit_id(&self) -> Result<ObjectId, GitError> {
@@ -107,39 +164,384 @@ impl Repository {
 
     pub fn remote_branch(&self, branch: &Branch) -> Result<Branch, GitError> {
         debug_assert!(matches!(branch, Branch::Local(..)));
-        self.0
+        self.repo
             .branch_remote_ref(branch.short_name())
             .ok_or(GitError::RepositoryRemoteNotFound)?
             .map(|r| Branch::Remote(r.shorten().to_string()))
             .map_err(|_| GitError::RepositoryRemoteInvalid)
     }
 
+    pub fn work_dir(&self) -> Option<&Path> {
+        self.repo.work_dir()
+    }
+
+    pub fn remote_url(&self) -> Result<String, GitError> {
+        self.remote()?
+            .url(Direction::Fetch)
+            .map(|url| url.to_bstring().to_string())
+            .ok_or(GitError::RepositoryRemoteInvalid)
+    }
+
+    /// The configured remote, or else the default remote for fetching.
     pub fn remote(&self) -> Result<Remote, GitError> {
-        self.0
+        if let Some(name) = &self.remote_name {
+            return self
+                .repo
+                .find_remote(name.as_str())
+                .map_err(|_| GitError::RepositoryRemoteMissing(name.clone()));
+        }
+
+        self.repo
             .find_default_remote(Direction::Fetch)
             .ok_or(GitError::RepositoryDefaultRemoteNotConfigured)?
             .map_err(|_| GitError::RepositoryDefaultRemoteMissing)
     }
 
+    /// The host of the URL that is fetched from, if it has one.
+    pub fn remote_host(&self) -> Result<Option<String>, GitError> {
+        Ok(fetch_host(&self.remote()?))
+    }
+
+    /// Fetch from the remote without writing any objects or references.
     pub fn fetch(&self, credentials: Option<&Credentials>) -> Result<Outcome, GitError> {
-        self.remote()?
-            .connect(Direction::Fetch, gix::progress::Discard)
-            .map_err(GitError::FetchConnect)?
-            .with_credentials(|action| match action {
-                Action::Get(ctx) => Ok(credentials.map(|c| protocol::Outcome {
-                    identity: Account {
-                        username: c.username.clone(),
-                        password: c.password.clone(),
-                    },
-                    next: ctx.into(),
-                })),
-                Action::Store(_) => Ok(None),
-                Action::Erase(_) => Ok(None),
+        self.fetch_with_options(credentials, Default::default(), true)
+    }
+
+    /// Fetch from the remote, receiving the objects and updating the
+    /// remote-tracking references.
+    pub fn fetch_and_update(&self, credentials: Option<&Credentials>) -> Result<Outcome, GitError> {
+        self.fetch_with_options(credentials, Default::default(), false)
+    }
+
+    /// Like `fetch`, but lists every reference on the remote instead of only
+    /// those matching the fetch refspecs of the remote (e.g. tags).
+    pub fn fetch_all_refs(&self, credentials: Option<&Credentials>) -> Result<Outcome, GitError> {
+        self.fetch_with_options(
+            credentials,
+            ref_map::Options {
+                prefix_from_spec_as_filter_on_remote: false,
+                ..Default::default()
+            },
+            true,
+        )
+    }
+
+    fn fetch_with_options(
+        &self,
+        credentials: Option<&Credentials>,
+        options: ref_map::Options,
+        dry_run: bool,
+    ) -> Result<Outcome, GitError> {
+        fetch_from(self.remote()?, credentials, options, dry_run)
+    }
+
+    /// Whether `ancestor` can be reached from `descendant`.
+    pub fn is_ancestor(&self, ancestor: ObjectId, descendant: ObjectId) -> Result<bool, GitError> {
+        let walk = self
+            .repo
+            .rev_walk(Some(descendant))
+            .all()
+            .map_err(GitError::RevisionWalk)?;
+
+        for id in walk {
+            if id.map_err(GitError::RevisionWalkTraverse)?.detach() == ancestor {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Update the working tree and index to `new_id`, then point the local
+    /// `branch`, or HEAD itself if it is detached, at it, provided it still
+    /// points at `previous_id`.
+    ///
+    /// Files that differ between the two commits are overwritten, and local
+    /// changes to them are lost. Other files are left as they are.
+    pub fn checkout(
+        &self,
+        branch: Option<&Branch>,
+        previous_id: ObjectId,
+        new_id: ObjectId,
+    ) -> Result<(), GitError> {
+        debug_assert!(branch.map_or(true, |b| matches!(b, Branch::Local(..))));
+
+        let work_dir = self.work_dir().ok_or(GitError::WorkTreeMissing)?;
+        let previous_tree = self.commit_tree(previous_id)?;
+        let new_tree = self.commit_tree(new_id)?;
+
+        // Nothing is written if the reference has moved since `previous_id`
+        // was read.
+        let name = branch.map_or_else(|| String::from("HEAD"), |b| b.as_reference().full_name());
+        let current_id = self
+            .repo
+            .find_reference(name.as_str())
+            .map_err(GitError::ReferenceMissing)?
+            .id()
+            .detach();
+        if current_id != previous_id {
+            return Err(GitError::ReferenceMoved(name));
+        }
+
+        // The working tree and the index are written before the reference is
+        // moved, so a failure leaves the reference at `previous_id`.
+        let mut written = HashSet::new();
+        previous_tree
+            .changes()
+            .track_path()
+            .for_each_to_obtain_tree(&new_tree, |change| {
+                let path = work_dir.join(gix::path::from_bstr(change.location).as_ref());
+                match change.event {
+                    diff::change::Event::Addition { entry_mode, id }
+                    | diff::change::Event::Modification { entry_mode, id, .. } => {
+                        let data = id
+                            .object()
+                            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?
+                            .data;
+                        write_entry(&path, entry_mode, &data)?;
+                        written.insert(change.location.to_owned());
+                    }
+                    diff::change::Event::Deletion { entry_mode, .. } => {
+                        remove_entry(&path, entry_mode)?;
+                    }
+                }
+                Ok::<_, io::Error>(diff::Action::Continue)
             })
-            .prepare_fetch(Default::default())
-            .map_err(GitError::FetchHandshake)?
-            .with_dry_run(true)
-            .receive(&gix::interrupt::IS_INTERRUPTED)
-            .map_err(GitError::FetchReceive)
+            .map_err(GitError::TreeDiff)?;
+
+        let mut state = index::State::from_tree(&new_tree.id, |id, buf| {
+            self.repo.objects.find_tree_iter(id, buf).ok()
+        })
+        .map_err(GitError::IndexFromTree)?;
+
+        // Entries without stat data are compared by content by Git, which
+        // is slow for large trees but never wrong.
+        for (entry, path) in state.entries_mut_with_paths() {
+            if written.contains(path) {
+                let metadata = work_dir
+                    .join(gix::path::from_bstr(path).as_ref())
+                    .symlink_metadata()
+                    .map_err(GitError::WorkTree)?;
+                entry.stat = entry_stat(&metadata);
+            }
+        }
+
+        index::File::from_state(state, self.repo.git_dir().join("index"))
+            .write(Default::default())
+            .map_err(GitError::IndexWrite)?;
+
+        self.repo
+            .reference(
+                name.as_str(),
+                new_id,
+                PreviousValue::MustExistAndMatch(Target::Peeled(previous_id)),
+                format!("outpost: update to {new_id}"),
+            )
+            .map_err(GitError::ReferenceEdit)?;
+
+        Ok(())
+    }
+
+    fn commit_tree(&self, id: ObjectId) -> Result<gix::Tree<'_>, GitError> {
+        self.repo
+            .find_object(id)
+            .map_err(GitError::ObjectMissing)?
+            .try_into_commit()
+            .map_err(GitError::ObjectNotACommit)?
+            .tree()
+            .map_err(GitError::CommitTree)
+    }
+}
+
+/// A remote repository that is watched without a checkout, by listing its
+/// references.
+pub struct RemoteRepository {
+    /// gix needs a repository to connect to a remote, even if nothing is
+    /// fetched into it.
+    scratch: gix::Repository,
+    url: String,
+}
+
+impl RemoteRepository {
+    /// `scratch` is an empty bare repository, which is created if it does
+    /// not exist.
+    pub fn open(scratch: &Path, url: String) -> Result<Self, GitError> {
+        let scratch = if scratch.exists() {
+            gix::open(scratch).map_err(GitError::ScratchOpen)?
+        } else {
+            gix::init_bare(scratch).map_err(GitError::ScratchInit)?
+        };
+
+        Ok(Self { scratch, url })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The host of `url`, if it has one.
+    pub fn host(&self) -> Result<Option<String>, GitError> {
+        let remote = self
+            .scratch
+            .remote_at(self.url.as_str())
+            .map_err(GitError::RemoteInit)?;
+        Ok(fetch_host(&remote))
+    }
+
+    /// The object that the reference `full_name` points to on the remote,
+    /// after peeling annotated tags, or `None` if it does not exist.
+    pub fn ls_remote(
+        &self,
+        full_name: &str,
+        credentials: Option<&Credentials>,
+    ) -> Result<Option<ObjectId>, GitError> {
+        // The refspec limits the listing to the watched reference. Nothing is
+        // written, as this is a dry run.
+        let remote = self
+            .scratch
+            .remote_at(self.url.as_str())
+            .and_then(|remote| {
+                remote.with_refspecs(
+                    Some(format!("{full_name}:refs/outpost/watched").as_str()),
+                    Direction::Fetch,
+                )
+            })
+            .map_err(GitError::RemoteInit)?;
+
+        let outcome = fetch_from(remote, credentials, Default::default(), true)?;
+
+        let id = outcome.ref_map.remote_refs.iter().find_map(|r| match r {
+            Ref::Direct {
+                full_ref_name,
+                object,
+            }
+            | Ref::Peeled {
+                full_ref_name,
+                object,
+                ..
+            }
+            | Ref::Symbolic {
+                full_ref_name,
+                object,
+                ..
+            } => (full_ref_name == full_name).then_some(*object),
+            Ref::Unborn { .. } => None,
+        });
+
+        Ok(id)
+    }
+}
+
+fn fetch_host(remote: &Remote) -> Option<String> {
+    remote
+        .url(Direction::Fetch)
+        .and_then(|url| url.host())
+        .map(str::to_string)
+}
+
+/// Connect to `remote` and fetch from it.
+///
+/// Without `credentials`, the credential helpers configured for Git, e.g. with
+/// `credential.helper`, are asked, and told whether the credentials they gave
+/// were accepted.
+fn fetch_from(
+    remote: Remote,
+    credentials: Option<&Credentials>,
+    options: ref_map::Options,
+    dry_run: bool,
+) -> Result<Outcome, GitError> {
+    let connection = remote
+        .connect(Direction::Fetch, gix::progress::Discard)
+        .map_err(GitError::FetchConnect)?;
+
+    let connection = match credentials {
+        // Nothing to store or erase, since they are not kept by a helper.
+        Some(credentials) => connection.with_credentials(|action| match action {
+            Action::Get(ctx) => Ok(Some(protocol::Outcome {
+                identity: Account {
+                    username: credentials.username.clone(),
+                    password: credentials.password.clone(),
+                },
+                next: ctx.into(),
+            })),
+            Action::Store(_) => Ok(None),
+            Action::Erase(_) => Ok(None),
+        }),
+        None => connection,
+    };
+
+    connection
+        .prepare_fetch(options)
+        .map_err(GitError::FetchHandshake)?
+        .with_dry_run(dry_run)
+        .receive(&gix::interrupt::IS_INTERRUPTED)
+        .map_err(GitError::FetchReceive)
+}
+
+fn write_entry(path: &Path, mode: EntryMode, data: &[u8]) -> Result<(), io::Error> {
+    match mode {
+        EntryMode::Blob | EntryMode::BlobExecutable | EntryMode::Link => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if path.is_dir() {
+                fs::remove_dir_all(path)?;
+            } else if path.symlink_metadata().is_ok() {
+                fs::remove_file(path)?;
+            }
+        }
+        // Directories are created along with the files in them, and
+        // submodules are not checked out.
+        EntryMode::Tree | EntryMode::Commit => return Ok(()),
+    }
+
+    if mode == EntryMode::Link {
+        let target = PathBuf::from(gix::path::from_bstr(data.as_bstr()).as_ref());
+        return std::os::unix::fs::symlink(target, path);
+    }
+
+    fs::write(path, data)?;
+
+    let permissions = if mode == EntryMode::BlobExecutable {
+        0o755
+    } else {
+        0o644
+    };
+    fs::set_permissions(
+        path,
+        std::os::unix::fs::PermissionsExt::from_mode(permissions),
+    )
+}
+
+/// The stat data of an index entry, truncated to 32 bits like Git does.
+fn entry_stat(metadata: &fs::Metadata) -> index::entry::Stat {
+    let time = |secs: i64, nsecs: i64| index::entry::Time {
+        secs: secs as u32,
+        nsecs: nsecs as u32,
+    };
+
+    index::entry::Stat {
+        mtime: time(metadata.mtime(), metadata.mtime_nsec()),
+        ctime: time(metadata.ctime(), metadata.ctime_nsec()),
+        dev: metadata.dev() as u32,
+        ino: metadata.ino() as u32,
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        size: metadata.size() as u32,
+    }
+}
+
+fn remove_entry(path: &Path, mode: EntryMode) -> Result<(), io::Error> {
+    let result = match mode {
+        EntryMode::Blob | EntryMode::BlobExecutable | EntryMode::Link => fs::remove_file(path),
+        // Only succeeds once every file in the directory has been removed.
+        EntryMode::Tree => fs::remove_dir(path),
+        EntryMode::Commit => return Ok(()),
+    };
+
+    match result {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(_) if mode == EntryMode::Tree => Ok(()),
+        result => result,
     }
 }
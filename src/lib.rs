@@ -1,7 +1,11 @@
 pub mod cli;
 pub mod config;
+pub mod control;
 pub mod database;
 pub mod fetch_and_compare;
 pub mod git;
+pub mod netrc;
+pub mod pattern;
 pub mod system;
+pub mod units;
 pub mod worker;
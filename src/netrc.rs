@@ -0,0 +1,265 @@
+/// The entries of a `.netrc` file, in the order they appear.
+///
+/// Only `machine`, `default`, `login`, `password` and `account` are
+/// supported; `account` is ignored. Values may be written in double quotes,
+/// with `\"` and `\\` escaped, to include whitespace. Lines starting with `#`
+/// are comments.
+#[derive(Debug)]
+pub struct Netrc {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    /// `None` for the `default` entry.
+    pub machine: Option<String>,
+    pub login: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum NetrcError {
+    /// A token other than the supported ones, e.g. `macdef`.
+    UnsupportedToken(String),
+    /// A token that is not followed by its value.
+    MissingValue(String),
+    /// `login`, `password` or `account` before any `machine` or `default`.
+    MissingMachine,
+    /// A quoted value without its closing quote.
+    UnterminatedQuote,
+}
+
+impl Netrc {
+    pub fn parse(content: &str) -> Result<Self, NetrcError> {
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut tokens = tokenize(content)?.into_iter();
+
+        while let Some(token) = tokens.next() {
+            let mut value = || {
+                tokens
+                    .next()
+                    .ok_or_else(|| NetrcError::MissingValue(token.clone()))
+            };
+
+            match token.as_str() {
+                "machine" => entries.push(Entry {
+                    machine: Some(value()?),
+                    login: None,
+                    password: None,
+                }),
+                "default" => entries.push(Entry {
+                    machine: None,
+                    login: None,
+                    password: None,
+                }),
+                "login" => {
+                    let login = value()?;
+                    entries.last_mut().ok_or(NetrcError::MissingMachine)?.login = Some(login);
+                }
+                "password" => {
+                    let password = value()?;
+                    entries
+                        .last_mut()
+                        .ok_or(NetrcError::MissingMachine)?
+                        .password = Some(password);
+                }
+                "account" => {
+                    value()?;
+                    entries.last().ok_or(NetrcError::MissingMachine)?;
+                }
+                _ => return Err(NetrcError::UnsupportedToken(token)),
+            }
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// The first entry for `host`, or else the `default` entry.
+    pub fn find(&self, host: Option<&str>) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|entry| host.is_some() && entry.machine.as_deref() == host)
+            .or_else(|| self.entries.iter().find(|entry| entry.machine.is_none()))
+    }
+}
+
+/// Split `content` at whitespace, except within double quotes, and without
+/// comments.
+fn tokenize(content: &str) -> Result<Vec<String>, NetrcError> {
+    let mut tokens = Vec::new();
+
+    for line in content.lines() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let mut chars = line.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+
+            let mut token = String::new();
+            if c == '"' {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            token.push(chars.next().ok_or(NetrcError::UnterminatedQuote)?)
+                        }
+                        Some(c) => token.push(c),
+                        None => return Err(NetrcError::UnterminatedQuote),
+                    }
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+            }
+            tokens.push(token);
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(netrc: &Netrc, host: Option<&str>) -> Option<(String, String)> {
+        let entry = netrc.find(host)?;
+        Some((entry.login.clone()?, entry.password.clone()?))
+    }
+
+    fn pair(login: &str, password: &str) -> Option<(String, String)> {
+        Some((login.to_string(), password.to_string()))
+    }
+
+    #[test]
+    fn finds_the_entry_of_the_host() {
+        let netrc = Netrc::parse(
+            "machine github.com login alice password ghp_1\n\
+             machine gitlab.com\n  login bob\n  password glpat_2\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            credentials(&netrc, Some("github.com")),
+            pair("alice", "ghp_1")
+        );
+        assert_eq!(
+            credentials(&netrc, Some("gitlab.com")),
+            pair("bob", "glpat_2")
+        );
+        assert!(netrc.find(Some("example.com")).is_none());
+        assert!(netrc.find(None).is_none());
+    }
+
+    #[test]
+    fn falls_back_to_the_default_entry() {
+        let netrc = Netrc::parse(
+            "default login anonymous password guest\n\
+             machine github.com login alice password ghp_1",
+        )
+        .unwrap();
+
+        // The entry of the host takes precedence, wherever the default is.
+        assert_eq!(
+            credentials(&netrc, Some("github.com")),
+            pair("alice", "ghp_1")
+        );
+        assert_eq!(
+            credentials(&netrc, Some("example.com")),
+            pair("anonymous", "guest")
+        );
+        assert_eq!(credentials(&netrc, None), pair("anonymous", "guest"));
+    }
+
+    #[test]
+    fn uses_the_first_entry_of_a_host() {
+        let netrc = Netrc::parse(
+            "machine github.com login first password 1\n\
+             machine github.com login second password 2",
+        )
+        .unwrap();
+        assert_eq!(credentials(&netrc, Some("github.com")), pair("first", "1"));
+    }
+
+    #[test]
+    fn ignores_account_and_comments() {
+        let netrc = Netrc::parse(
+            "# Deploy keys\n\
+             machine github.com account org login alice password ghp_1\n\
+             \x20 # machine gitlab.com login bob password 2\n",
+        )
+        .unwrap();
+        assert_eq!(
+            credentials(&netrc, Some("github.com")),
+            pair("alice", "ghp_1")
+        );
+        assert!(netrc.find(Some("gitlab.com")).is_none());
+    }
+
+    #[test]
+    fn reads_quoted_values() {
+        let netrc =
+            Netrc::parse(r#"machine github.com login "alice smith" password "p#ss \"w\\rd\"""#)
+                .unwrap();
+        assert_eq!(
+            credentials(&netrc, Some("github.com")),
+            pair("alice smith", r#"p#ss "w\rd""#)
+        );
+
+        let empty = Netrc::parse(r#"machine github.com login alice password """#).unwrap();
+        assert_eq!(credentials(&empty, Some("github.com")), pair("alice", ""));
+    }
+
+    #[test]
+    fn entries_may_lack_login_or_password() {
+        let netrc = Netrc::parse("machine github.com login alice").unwrap();
+        let entry = netrc.find(Some("github.com")).unwrap();
+        assert_eq!(entry.login.as_deref(), Some("alice"));
+        assert_eq!(entry.password, None);
+    }
+
+    #[test]
+    fn accepts_empty_files() {
+        assert!(Netrc::parse("").unwrap().find(None).is_none());
+        assert!(Netrc::parse("\n# nothing\n").unwrap().find(None).is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(matches!(
+            Netrc::parse("machine a macdef init"),
+            Err(NetrcError::UnsupportedToken(token)) if token == "macdef"
+        ));
+        assert!(matches!(
+            Netrc::parse("machine a login"),
+            Err(NetrcError::MissingValue(token)) if token == "login"
+        ));
+        assert!(matches!(
+            Netrc::parse("machine"),
+            Err(NetrcError::MissingValue(_))
+        ));
+        assert!(matches!(
+            Netrc::parse("login alice password secret"),
+            Err(NetrcError::MissingMachine)
+        ));
+        assert!(matches!(
+            Netrc::parse(r#"machine a password "secret"#),
+            Err(NetrcError::UnterminatedQuote)
+        ));
+        assert!(matches!(
+            Netrc::parse(r#"machine a password "secret\"#),
+            Err(NetrcError::UnterminatedQuote)
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A glob pattern for reference names, e.g. `release/*`.
+///
+/// `*` matches any sequence of characters (including `/`) and `?` matches
+/// exactly one character. Every other character matches itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Pattern(String);
+
+impl Pattern {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self(pattern.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let pattern: Vec<char> = self.0.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+
+        // Position in `pattern` and `name` right after the last `*`, to
+        // backtrack to when the remainder does not match.
+        let mut backtrack = None;
+        let (mut p, mut n) = (0, 0);
+
+        while n < name.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    p += 1;
+                    backtrack = Some((p, n));
+                }
+                Some('?') => {
+                    p += 1;
+                    n += 1;
+                }
+                Some(&c) if c == name[n] => {
+                    p += 1;
+                    n += 1;
+                }
+                _ => match backtrack {
+                    Some((star_p, star_n)) => {
+                        p = star_p;
+                        n = star_n + 1;
+                        backtrack = Some((star_p, star_n + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        pattern[p..].iter().all(|&c| c == '*')
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(value: Pattern) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        Pattern::new(pattern).matches(name)
+    }
+
+    #[test]
+    fn literal_matches_itself_only() {
+        assert!(matches("main", "main"));
+        assert!(!matches("main", "main2"));
+        assert!(!matches("main", "mai"));
+        assert!(!matches("main", ""));
+        assert!(matches("", ""));
+        assert!(!matches("", "main"));
+    }
+
+    #[test]
+    fn star_matches_any_sequence_including_slashes() {
+        assert!(matches("release/*", "release/1.0"));
+        assert!(matches("release/*", "release/"));
+        assert!(matches("release/*", "release/1.0/hotfix"));
+        assert!(!matches("release/*", "releases/1.0"));
+        assert!(matches("*", ""));
+        assert!(matches("*", "feature/x"));
+        assert!(matches("**", "anything"));
+    }
+
+    #[test]
+    fn star_backtracks() {
+        assert!(matches("*-rc", "1.0-rc-rc"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(!matches("a*b*c", "aXbYbZ"));
+        assert!(matches("*/*/fix", "team/alice/fix"));
+        assert!(!matches("*/fix", "team/fixes"));
+    }
+
+    #[test]
+    fn question_mark_matches_exactly_one_character() {
+        assert!(matches("v?", "v1"));
+        assert!(!matches("v?", "v"));
+        assert!(!matches("v?", "v10"));
+        assert!(matches("v??", "vé1"));
+    }
+}
@@ -1,10 +1,25 @@
+use serde::{Deserialize, Serialize};
+
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+mod signal;
 
 #[cfg(target_os = "linux")]
-pub use linux::is_process_running;
+pub use linux::{is_process_running, process_identity};
 
 #[cfg(target_os = "macos")]
-pub use macos::is_process_running;
+pub use macos::{is_process_running, process_identity};
+
+pub use signal::{process_group_exists, send_signal, send_signal_to_group, Signal};
+
+/// Properties of a process that, together with its ID, tell it apart from
+/// a later process that has been assigned the same ID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessIdentity {
+    /// The start time of the process, in a platform-specific unit.
+    pub start_time: u64,
+    /// The (possibly truncated) name of the executable.
+    pub name: String,
+}
@@ -1,7 +1,18 @@
 use procfs::process::Process;
 
+use super::ProcessIdentity;
+
 pub fn is_process_running(id: u32) -> bool {
     Process::new(id as i32)
         .map(|p| p.is_alive())
         .unwrap_or(false)
 }
+
+pub fn process_identity(id: u32) -> Option<ProcessIdentity> {
+    let stat = Process::new(id as i32).and_then(|p| p.stat()).ok()?;
+
+    Some(ProcessIdentity {
+        start_time: stat.starttime,
+        name: stat.comm,
+    })
+}
@@ -1,5 +1,17 @@
-use libproc::libproc::task_info::TaskAllInfo;
+use libproc::libproc::{bsd_info::BSDInfo, proc_pid, task_info::TaskAllInfo};
+
+use super::ProcessIdentity;
 
 pub fn is_process_running(id: u32) -> bool {
     libproc::libproc::proc_pid::pidinfo::<TaskAllInfo>(id as i32, 0).is_ok()
 }
+
+pub fn process_identity(id: u32) -> Option<ProcessIdentity> {
+    let info = proc_pid::pidinfo::<BSDInfo>(id as i32, 0).ok()?;
+    let name = proc_pid::name(id as i32).ok()?;
+
+    Some(ProcessIdentity {
+        start_time: info.pbi_start_tvsec,
+        name,
+    })
+}
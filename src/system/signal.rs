@@ -0,0 +1,55 @@
+use std::io;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Signal {
+    Hangup,
+    Terminate,
+    Kill,
+}
+
+impl Signal {
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            Self::Hangup => libc::SIGHUP,
+            Self::Terminate => libc::SIGTERM,
+            Self::Kill => libc::SIGKILL,
+        }
+    }
+}
+
+pub fn send_signal(id: u32, signal: Signal) -> Result<(), io::Error> {
+    kill(id as libc::pid_t, signal)
+}
+
+/// Send `signal` to every process in the process group `id`.
+pub fn send_signal_to_group(id: u32, signal: Signal) -> Result<(), io::Error> {
+    kill(-(id as libc::pid_t), signal)
+}
+
+/// Whether the process group `id` has any process that could be signalled.
+pub fn process_group_exists(id: u32) -> Result<bool, io::Error> {
+    // No signal is sent, but the group is still checked.
+    match raw_kill(-(id as libc::pid_t), 0) {
+        Ok(()) => Ok(true),
+        Err(error) if matches!(error.raw_os_error(), Some(libc::ESRCH) | Some(libc::EPERM)) => {
+            Ok(false)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+fn kill(id: libc::pid_t, signal: Signal) -> Result<(), io::Error> {
+    raw_kill(id, signal.as_raw())
+}
+
+fn raw_kill(id: libc::pid_t, signal: libc::c_int) -> Result<(), io::Error> {
+    // SAFETY: `kill` has no memory safety requirements; an invalid ID is
+    // reported through the return value.
+    let result = unsafe { libc::kill(id, signal) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
@@ -0,0 +1,224 @@
+use std::{fmt, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// A duration written as a number followed by a unit, e.g. `30d`.
+///
+/// The units are `s`, `m`, `h`, `d` and `w`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Age(Duration);
+
+impl Age {
+    pub fn as_duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl TryFrom<String> for Age {
+    type Error = UnitError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (number, unit) = split_unit(&value)?;
+        let multiplier = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            "w" => 7 * 24 * 60 * 60,
+            _ => return Err(UnitError::UnknownUnit(value)),
+        };
+
+        number
+            .checked_mul(multiplier)
+            .map(|seconds| Self(Duration::from_secs(seconds)))
+            .ok_or(UnitError::TooLarge(value))
+    }
+}
+
+impl From<Age> for String {
+    fn from(value: Age) -> Self {
+        format!("{}s", value.0.as_secs())
+    }
+}
+
+/// A number of bytes, either as an integer or as a number followed by a
+/// unit, e.g. `500M`.
+///
+/// The units are `K`, `M`, `G` and `T`, each 1024 times the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "SizeRepr", into = "String")]
+pub struct Size(u64);
+
+impl Size {
+    pub fn as_bytes(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SizeRepr {
+    Bytes(u64),
+    Text(String),
+}
+
+impl TryFrom<SizeRepr> for Size {
+    type Error = UnitError;
+
+    fn try_from(value: SizeRepr) -> Result<Self, Self::Error> {
+        let value = match value {
+            SizeRepr::Bytes(bytes) => return Ok(Self(bytes)),
+            SizeRepr::Text(value) => value,
+        };
+
+        if let Ok(bytes) = value.parse() {
+            return Ok(Self(bytes));
+        }
+
+        let (number, unit) = split_unit(&value)?;
+        let exponent = match unit {
+            "K" => 1,
+            "M" => 2,
+            "G" => 3,
+            "T" => 4,
+            _ => return Err(UnitError::UnknownUnit(value)),
+        };
+
+        number
+            .checked_mul(1024u64.pow(exponent))
+            .map(Self)
+            .ok_or(UnitError::TooLarge(value))
+    }
+}
+
+impl From<Size> for String {
+    fn from(value: Size) -> Self {
+        value.0.to_string()
+    }
+}
+
+#[derive(Debug)]
+pub enum UnitError {
+    InvalidNumber(String),
+    UnknownUnit(String),
+    TooLarge(String),
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidNumber(value) => write!(f, "`{value}` does not start with a number"),
+            Self::UnknownUnit(value) => write!(f, "`{value}` does not end with a known unit"),
+            Self::TooLarge(value) => write!(f, "`{value}` is too large"),
+        }
+    }
+}
+
+fn split_unit(value: &str) -> Result<(u64, &str), UnitError> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number = number
+        .parse()
+        .map_err(|_| UnitError::InvalidNumber(value.to_string()))?;
+
+    Ok((number, unit.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn age(value: &str) -> Result<Age, UnitError> {
+        Age::try_from(value.to_string())
+    }
+
+    fn size(value: &str) -> Result<Size, UnitError> {
+        Size::try_from(SizeRepr::Text(value.to_string()))
+    }
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(age("30s").unwrap().as_duration(), Duration::from_secs(30));
+        assert_eq!(age("5m").unwrap().as_duration(), Duration::from_secs(300));
+        assert_eq!(age("2h").unwrap().as_duration(), Duration::from_secs(7200));
+        assert_eq!(
+            age("30d").unwrap().as_duration(),
+            Duration::from_secs(2_592_000)
+        );
+        assert_eq!(
+            age("1w").unwrap().as_duration(),
+            Duration::from_secs(604_800)
+        );
+        assert_eq!(age("0d").unwrap().as_duration(), Duration::ZERO);
+        assert_eq!(
+            age("3 d").unwrap().as_duration(),
+            Duration::from_secs(259_200)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_ages() {
+        assert!(matches!(age("30"), Err(UnitError::UnknownUnit(_))));
+        assert!(matches!(age("30y"), Err(UnitError::UnknownUnit(_))));
+        assert!(matches!(age("30D"), Err(UnitError::UnknownUnit(_))));
+        assert!(matches!(age("d"), Err(UnitError::InvalidNumber(_))));
+        assert!(matches!(age(""), Err(UnitError::InvalidNumber(_))));
+        assert!(matches!(age("-1d"), Err(UnitError::InvalidNumber(_))));
+        assert!(matches!(
+            age("99999999999999999999d"),
+            Err(UnitError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            age("18446744073709551615w"),
+            Err(UnitError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn age_round_trips_as_seconds() {
+        let value: String = age("2d").unwrap().into();
+        assert_eq!(value, "172800s");
+        assert_eq!(age(&value).unwrap(), age("2d").unwrap());
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(
+            Size::try_from(SizeRepr::Bytes(500)).unwrap().as_bytes(),
+            500
+        );
+        assert_eq!(size("500").unwrap().as_bytes(), 500);
+        assert_eq!(size("1K").unwrap().as_bytes(), 1024);
+        assert_eq!(size("500M").unwrap().as_bytes(), 500 * 1024 * 1024);
+        assert_eq!(size("2G").unwrap().as_bytes(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(size("1T").unwrap().as_bytes(), 1024u64.pow(4));
+        assert_eq!(size("1 G").unwrap().as_bytes(), 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        assert!(matches!(size("1k"), Err(UnitError::UnknownUnit(_))));
+        assert!(matches!(size("1P"), Err(UnitError::UnknownUnit(_))));
+        assert!(matches!(size("1GB"), Err(UnitError::UnknownUnit(_))));
+        assert!(matches!(size("G"), Err(UnitError::InvalidNumber(_))));
+        assert!(matches!(size(""), Err(UnitError::InvalidNumber(_))));
+        assert!(matches!(size("20000000T"), Err(UnitError::TooLarge(_))));
+    }
+
+    #[test]
+    fn sizes_deserialize_from_numbers_and_strings() {
+        #[derive(Deserialize)]
+        struct Limits {
+            number: Size,
+            text: Size,
+        }
+
+        let limits: Limits = toml::from_str("number = 2048\ntext = \"2K\"").unwrap();
+        assert_eq!(limits.number, limits.text);
+        assert!(toml::from_str::<Limits>("number = 1\ntext = \"2X\"").is_err());
+    }
+}
@@ -1,3 +1,10 @@
+mod control;
+mod failure;
+mod hook;
+mod notify;
 mod poll;
+mod retention;
+mod status;
+mod webhook;
 
-pub use poll::poll;
+pub use poll::{listen, poll};
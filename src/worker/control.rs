@@ -0,0 +1,222 @@
+use std::{
+    fs, io,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use time::OffsetDateTime;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, Notify},
+};
+
+use super::poll::PollError;
+use crate::control::{socket_path, Request, Response, WorkerState, WorkerStatus};
+
+/// What the poll loop is asked to do through the control socket.
+#[derive(Debug)]
+pub enum Command {
+    Pause,
+    Resume,
+    Shutdown,
+}
+
+/// Shared by the poll loop and the control socket.
+pub struct Control {
+    status: Mutex<WorkerStatus>,
+    /// Wakes the poll loop up for a check.
+    wake: Arc<Notify>,
+    /// Whether the next check should run the update even if nothing has
+    /// changed.
+    force: AtomicBool,
+    /// Whether the configuration has an update that can be forced.
+    force_supported: AtomicBool,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Control {
+    pub fn new(
+        directory: String,
+        wake: Arc<Notify>,
+    ) -> (Arc<Self>, mpsc::UnboundedReceiver<Command>) {
+        let (commands, receiver) = mpsc::unbounded_channel();
+
+        let control = Self {
+            status: Mutex::new(WorkerStatus {
+                directory,
+                process_id: std::process::id(),
+                state: WorkerState::Idle,
+                last_check: None,
+                next_check: None,
+                consecutive_failures: 0,
+            }),
+            wake,
+            force: AtomicBool::new(false),
+            force_supported: AtomicBool::new(false),
+            commands,
+        };
+
+        (Arc::new(control), receiver)
+    }
+
+    pub fn checking(&self) {
+        let mut status = self.status.lock().unwrap();
+        status.state = WorkerState::Checking;
+        status.last_check = Some(OffsetDateTime::now_utc().unix_timestamp());
+        status.next_check = None;
+    }
+
+    /// `delay` is `None` while the worker is paused.
+    pub fn waiting(&self, delay: Option<Duration>, consecutive_failures: u32) {
+        let mut status = self.status.lock().unwrap();
+        status.consecutive_failures = consecutive_failures;
+        match delay {
+            Some(delay) => {
+                status.state = WorkerState::Idle;
+                status.next_check =
+                    Some(OffsetDateTime::now_utc().unix_timestamp() + delay.as_secs() as i64);
+            }
+            None => {
+                status.state = WorkerState::Paused;
+                status.next_check = None;
+            }
+        }
+    }
+
+    /// Set whenever the configuration is read, so that `trigger --force` is
+    /// refused when it would not run anything.
+    pub fn set_force_supported(&self, supported: bool) {
+        self.force_supported.store(supported, Ordering::SeqCst);
+    }
+
+    /// Whether a forced update was requested since the last call.
+    pub fn take_force(&self) -> bool {
+        self.force.swap(false, Ordering::SeqCst)
+    }
+
+    fn respond(&self, request: Request) -> Response {
+        let command = match request {
+            Request::Status => return Response::Status(self.status.lock().unwrap().clone()),
+            Request::Trigger { force } => {
+                if self.status.lock().unwrap().state == WorkerState::Paused {
+                    return Response::Error {
+                        message: "The worker is paused. Resume it first.".to_string(),
+                    };
+                }
+                if force && !self.force_supported.load(Ordering::SeqCst) {
+                    return Response::Error {
+                        message: "Forcing an update is not supported when only tags are watched."
+                            .to_string(),
+                    };
+                }
+                if force {
+                    self.force.store(true, Ordering::SeqCst);
+                }
+                self.wake.notify_one();
+                return Response::Ok;
+            }
+            Request::Pause => Command::Pause,
+            Request::Resume => Command::Resume,
+            Request::Shutdown => Command::Shutdown,
+        };
+
+        // Only fails once the poll loop has stopped.
+        match self.commands.send(command) {
+            Ok(()) => Response::Ok,
+            Err(_) => Response::Error {
+                message: "The worker is stopping.".to_string(),
+            },
+        }
+    }
+}
+
+/// Removes the control socket when dropped.
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            tracing::warn!(?error, "Failed to remove `{}`", self.path.display());
+        }
+    }
+}
+
+/// Start answering requests on the control socket of the worker in the
+/// background.
+///
+/// Returns `None` if the home directory is not found. Must be called from
+/// within the runtime.
+pub fn serve(control: Arc<Control>) -> Result<Option<ControlSocket>, PollError> {
+    let directory = control.status.lock().unwrap().directory.clone();
+    let Some(path) = socket_path(&directory) else {
+        tracing::warn!("Home directory not found. The control socket is not available.");
+        return Ok(None);
+    };
+
+    // Anyone who can connect can control the worker.
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(PollError::ControlSocket)?;
+        fs::set_permissions(parent, fs::Permissions::from_mode(0o700))
+            .map_err(PollError::ControlSocket)?;
+    }
+    // Left behind by a worker that did not exit cleanly. `start` makes sure
+    // that no other worker runs in the same directory.
+    match fs::remove_file(&path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => {
+            return Err(PollError::ControlSocket(error))
+        }
+        _ => {}
+    }
+
+    let listener = UnixListener::bind(&path).map_err(PollError::ControlSocket)?;
+    tracing::debug!("Listening on `{}`.", path.display());
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle(control.clone(), stream));
+                }
+                Err(error) => {
+                    tracing::warn!(?error, "Failed to accept a control connection");
+                }
+            }
+        }
+    });
+
+    Ok(Some(ControlSocket { path }))
+}
+
+async fn handle(control: Arc<Control>, stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+
+    let mut line = String::new();
+    if let Err(error) = BufReader::new(reader).read_line(&mut line).await {
+        tracing::warn!(?error, "Failed to read a control request");
+        return;
+    }
+
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => {
+            tracing::debug!(?request, "Control request received.");
+            control.respond(request)
+        }
+        Err(error) => Response::Error {
+            message: format!("Invalid request: {error}"),
+        },
+    };
+
+    let mut line = serde_json::to_vec(&response).expect("failed to serialize response");
+    line.push(b'\n');
+    if let Err(error) = writer.write_all(&line).await {
+        tracing::warn!(?error, "Failed to send a control response");
+    }
+}
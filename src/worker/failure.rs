@@ -0,0 +1,216 @@
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+use super::{poll::PollError, status::StatusRecorder};
+use crate::{
+    config::{FailureConfig, FetchFailurePolicy, HookFailurePolicy},
+    database::FailureState,
+};
+
+/// How the worker carries on after a failed check.
+pub enum Recovery {
+    /// Check again after the given delay instead of the usual interval.
+    Retry(Duration),
+    /// Check again after the usual interval.
+    Continue,
+    /// Do not check again until the worker is reloaded.
+    Pause,
+}
+
+/// Counts consecutive failures and records them in the worker status.
+pub struct FailureTracker {
+    state: Option<FailureState>,
+}
+
+impl FailureTracker {
+    /// Any failure recorded by a previous worker is cleared.
+    pub async fn new(status: &StatusRecorder) -> Self {
+        if status.read().await.failure().is_some() {
+            status.update(|s| s.set_failure(None)).await;
+        }
+
+        Self { state: None }
+    }
+
+    pub async fn succeeded(&mut self, status: &StatusRecorder) {
+        if self.state.take().is_some() {
+            tracing::info!("Recovered from the previous failure(s).");
+            status.update(|s| s.set_failure(None)).await;
+        }
+    }
+
+    /// Decide how to carry on after `error`, according to `config`.
+    ///
+    /// The error is returned if the worker should stop.
+    pub async fn failed(
+        &mut self,
+        config: &FailureConfig,
+        error: PollError,
+        status: &StatusRecorder,
+    ) -> Result<Recovery, PollError> {
+        let consecutive_failures = self.consecutive_failures() + 1;
+
+        let recovery = match &error {
+            PollError::Fetch(_) => match config.on_fetch_error {
+                FetchFailurePolicy::Stop => None,
+                FetchFailurePolicy::Retry => {
+                    Some(Recovery::Retry(backoff(config, consecutive_failures)))
+                }
+            },
+            error if is_hook_failure(error) => match config.on_hook_error {
+                HookFailurePolicy::Stop => None,
+                HookFailurePolicy::Continue => Some(Recovery::Continue),
+                HookFailurePolicy::Pause => Some(Recovery::Pause),
+            },
+            _ => None,
+        };
+
+        let recovery = recovery.filter(|_| {
+            config
+                .max_consecutive_failures
+                .map_or(true, |max| consecutive_failures < max)
+        });
+
+        let state = FailureState {
+            consecutive_failures,
+            last_failure: OffsetDateTime::now_utc().unix_timestamp(),
+            last_error: format!("{error:?}"),
+            paused: matches!(recovery, Some(Recovery::Pause)),
+        };
+        status.update(|s| s.set_failure(Some(state.clone()))).await;
+        self.state = Some(state);
+
+        match &recovery {
+            Some(Recovery::Retry(delay)) => {
+                tracing::warn!(
+                    ?error,
+                    consecutive_failures,
+                    "Check failed. Retrying in {} second(s).",
+                    delay.as_secs()
+                );
+            }
+            Some(Recovery::Continue) => {
+                tracing::warn!(?error, consecutive_failures, "Check failed. Continuing.");
+            }
+            Some(Recovery::Pause) => {
+                tracing::warn!(
+                    ?error,
+                    consecutive_failures,
+                    "Check failed. Pausing until the configuration is reloaded."
+                );
+            }
+            None => {}
+        }
+
+        recovery.ok_or(error)
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.state.as_ref().map_or(0, |s| s.consecutive_failures)
+    }
+
+    /// Called when a paused worker is reloaded or resumed.
+    pub async fn resumed(&mut self, status: &StatusRecorder) {
+        if let Some(state) = &mut self.state {
+            state.paused = false;
+            let state = state.clone();
+            status.update(|s| s.set_failure(Some(state))).await;
+        }
+    }
+}
+
+/// Whether `error` is the failure of an update, rather than of the worker
+/// itself. Several failed updates count as one if they all are.
+fn is_hook_failure(error: &PollError) -> bool {
+    match error {
+        PollError::Spawn { .. }
+        | PollError::Complete { .. }
+        | PollError::HookTimeout { .. }
+        | PollError::NonZeroExit { .. }
+        | PollError::BranchWasNotUpdated
+        | PollError::NotFastForward { .. }
+        | PollError::UnexpectedCommitId { .. } => true,
+        PollError::UpdatesFailed(errors) => errors.iter().all(is_hook_failure),
+        PollError::Config(_)
+        | PollError::CurrentDirectory(_)
+        | PollError::HomeDirectoryMissing
+        | PollError::Git(_)
+        | PollError::Fetch(_)
+        | PollError::Directory(_)
+        | PollError::File(_)
+        | PollError::Signal(_)
+        | PollError::ListenMissing
+        | PollError::Listen(_)
+        | PollError::ControlSocket(_) => false,
+    }
+}
+
+fn backoff(config: &FailureConfig, consecutive_failures: u32) -> Duration {
+    let factor = 2u64.saturating_pow(consecutive_failures - 1);
+    let seconds = config
+        .initial_backoff
+        .saturating_mul(factor)
+        .min(config.max_backoff);
+    Duration::from_secs(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(initial_backoff: u64, max_backoff: u64) -> FailureConfig {
+        FailureConfig {
+            initial_backoff,
+            max_backoff,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_after_every_failure() {
+        let config = config(5, 300);
+        let delays: Vec<u64> = (1..=6).map(|n| backoff(&config, n).as_secs()).collect();
+        assert_eq!(delays, [5, 10, 20, 40, 80, 160]);
+    }
+
+    #[test]
+    fn backoff_is_limited_by_max_backoff() {
+        let config = config(5, 300);
+        assert_eq!(backoff(&config, 7).as_secs(), 300);
+        assert_eq!(backoff(&config, 64).as_secs(), 300);
+        assert_eq!(backoff(&config, u32::MAX).as_secs(), 300);
+    }
+
+    #[test]
+    fn backoff_without_initial_delay_stays_zero() {
+        assert_eq!(backoff(&config(0, 300), 10), Duration::ZERO);
+    }
+
+    #[test]
+    fn backoff_never_exceeds_a_smaller_max() {
+        assert_eq!(backoff(&config(60, 30), 1).as_secs(), 30);
+    }
+
+    #[test]
+    fn failed_updates_are_hook_failures_only_if_all_are() {
+        let timeout = || PollError::HookTimeout {
+            hook: "hook".into(),
+            timeout: Duration::from_secs(1),
+        };
+        let exit = || PollError::NonZeroExit {
+            path: "update".to_string(),
+        };
+        let directory = || PollError::Directory(std::io::ErrorKind::PermissionDenied.into());
+
+        assert!(is_hook_failure(&PollError::UpdatesFailed(vec![
+            timeout(),
+            exit()
+        ])));
+        assert!(!is_hook_failure(&PollError::UpdatesFailed(vec![
+            exit(),
+            directory()
+        ])));
+        assert!(!is_hook_failure(&directory()));
+    }
+}
@@ -0,0 +1,664 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+    time::Duration,
+};
+
+use gix::ObjectId;
+use serde::Serialize;
+use time::macros::format_description;
+use tokio::process::Child;
+
+use super::{poll::PollError, status::StatusRecorder};
+use crate::{
+    config::{HookCommand, StepConfig},
+    database::{v1, Update, UpdateResult},
+    git::Repository,
+    system::{process_group_exists, send_signal_to_group, Signal},
+};
+
+/// How long a hook that timed out is given to exit after SIGTERM, before
+/// it is killed.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// What an update is about, passed to the hook through environment variables
+/// and written to `context.json` in the update directory.
+#[derive(Debug, Serialize)]
+pub struct UpdateContext {
+    /// `None` if there is no previous commit, e.g. for a new branch.
+    pub old_commit: Option<String>,
+    pub new_commit: String,
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    /// The commit of the last successful update of the branch or tag, or else
+    /// the commit before this update, if known.
+    pub good_commit: Option<String>,
+    pub remote_url: Option<String>,
+    pub repo_dir: Option<PathBuf>,
+    pub update_dir: PathBuf,
+    /// Whether the update was forced with `outpost trigger --force`, in which
+    /// case the commit may not have changed.
+    pub manual: bool,
+}
+
+impl UpdateContext {
+    pub fn new(repo: &Repository, update_dir: PathBuf, new_commit: ObjectId) -> Self {
+        Self {
+            old_commit: None,
+            new_commit: new_commit.to_string(),
+            branch: None,
+            tag: None,
+            good_commit: None,
+            remote_url: repo.remote_url().ok(),
+            repo_dir: repo.work_dir().map(Path::to_path_buf),
+            update_dir,
+            manual: false,
+        }
+    }
+
+    /// For a remote repository that is watched without a checkout.
+    pub fn for_remote(url: &str, update_dir: PathBuf, new_commit: ObjectId) -> Self {
+        Self {
+            old_commit: None,
+            new_commit: new_commit.to_string(),
+            branch: None,
+            tag: None,
+            good_commit: None,
+            remote_url: Some(url.to_string()),
+            repo_dir: None,
+            update_dir,
+            manual: false,
+        }
+    }
+
+    /// The full name of the branch or tag, or `HEAD` if there is neither,
+    /// under which the good commit is recorded.
+    pub fn reference(&self) -> String {
+        match (&self.branch, &self.tag) {
+            (Some(branch), _) => format!("refs/heads/{branch}"),
+            (None, Some(tag)) => format!("refs/tags/{tag}"),
+            (None, None) => "HEAD".to_string(),
+        }
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("OUTPOST_NEW_COMMIT", self.new_commit.clone()),
+            ("OUTPOST_UPDATE_DIR", self.update_dir.display().to_string()),
+        ];
+
+        let optional = [
+            ("OUTPOST_OLD_COMMIT", self.old_commit.clone()),
+            ("OUTPOST_BRANCH", self.branch.clone()),
+            ("OUTPOST_TAG", self.tag.clone()),
+            ("OUTPOST_GOOD_COMMIT", self.good_commit.clone()),
+            ("OUTPOST_REMOTE_URL", self.remote_url.clone()),
+            (
+                "OUTPOST_REPO_DIR",
+                self.repo_dir.as_ref().map(|d| d.display().to_string()),
+            ),
+            ("OUTPOST_MANUAL", self.manual.then(|| String::from("1"))),
+        ];
+
+        env.extend(
+            optional
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, value?))),
+        );
+
+        env
+    }
+}
+
+/// Create a new directory for the output of an update under `updates`.
+///
+/// The directory is named after the current time, followed by `label` if
+/// one is given.
+pub fn create_update_directory(updates: &Path, label: Option<&str>) -> Result<PathBuf, PollError> {
+    let format = format_description!("[year]-[month]-[day]_[hour]-[minute]-[second]");
+    let mut name = time::OffsetDateTime::now_utc()
+        .format(format)
+        .expect("invalid format");
+
+    if let Some(label) = label {
+        name.push('_');
+        name.push_str(&label.replace('/', "-"));
+    }
+
+    let path = updates.join(name);
+
+    tracing::debug!("Creating `{}`", path.display());
+
+    std::fs::create_dir_all(&path).map_err(PollError::Directory)?;
+
+    Ok(path)
+}
+
+/// What to run for an update.
+pub enum Pipeline<'a> {
+    /// A single hook, with its output written directly to the update
+    /// directory. `shell` runs `HookCommand::Shell` hooks.
+    Hook {
+        command: &'a HookCommand,
+        shell: Vec<&'a str>,
+        args: &'a [&'a str],
+    },
+    /// Steps run one after the other, each with its output written to its own
+    /// directory within the update directory.
+    Steps(&'a [StepConfig]),
+}
+
+struct Step<'a> {
+    name: &'a str,
+    command: &'a Path,
+    args: Vec<&'a str>,
+    working_dir: Option<&'a Path>,
+    env: Option<&'a BTreeMap<String, String>>,
+    timeout: Option<Duration>,
+    continue_on_error: bool,
+    output_dir: PathBuf,
+}
+
+impl<'a> Pipeline<'a> {
+    fn steps(&self, update_dir: &Path, timeout: Option<Duration>) -> Vec<Step<'a>> {
+        match self {
+            Pipeline::Hook {
+                command,
+                shell,
+                args,
+            } => vec![hook_step(
+                "on_update",
+                command,
+                shell,
+                args,
+                update_dir.to_path_buf(),
+                timeout,
+            )],
+            Pipeline::Steps(steps) => steps
+                .iter()
+                .enumerate()
+                .map(|(index, step)| Step {
+                    name: &step.name,
+                    command: &step.command,
+                    args: step.args.iter().map(String::as_str).collect(),
+                    working_dir: step.working_dir.as_deref(),
+                    env: Some(&step.env),
+                    timeout: step.timeout().or(timeout),
+                    continue_on_error: step.continue_on_error,
+                    output_dir: update_dir.join(format!(
+                        "{:02}_{}",
+                        index + 1,
+                        step.name.replace('/', "-")
+                    )),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Run `command`, or `shell` with `command` as the script, followed by
+/// `args`.
+fn hook_step<'a>(
+    name: &'a str,
+    command: &'a HookCommand,
+    shell: &[&'a str],
+    args: &[&'a str],
+    output_dir: PathBuf,
+    timeout: Option<Duration>,
+) -> Step<'a> {
+    let (program, mut argv) = hook_argv(command, shell);
+    argv.extend_from_slice(args);
+
+    Step {
+        name,
+        command: program,
+        args: argv,
+        working_dir: None,
+        env: None,
+        timeout,
+        continue_on_error: false,
+        output_dir,
+    }
+}
+
+/// The program that runs `command`, and its arguments.
+pub fn hook_argv<'a>(command: &'a HookCommand, shell: &[&'a str]) -> (&'a Path, Vec<&'a str>) {
+    match command {
+        HookCommand::Path(path) => (path, Vec::new()),
+        HookCommand::Argv(argv) => (
+            argv[0].as_ref(),
+            argv[1..].iter().map(String::as_str).collect(),
+        ),
+        // The script is followed by `$0` for `sh -c`, so that any further
+        // arguments start at `$1`.
+        HookCommand::Shell { shell: script } => (
+            shell[0].as_ref(),
+            shell[1..]
+                .iter()
+                .copied()
+                .chain([script.as_str(), "outpost"])
+                .collect(),
+        ),
+    }
+}
+
+/// An update in the update history that has not finished yet, so that steps
+/// run after the pipeline, like the `on_failure` hook, are recorded before
+/// `logs --follow` considers it done.
+pub struct RecordedUpdate {
+    id: Option<u64>,
+    exit_code: Option<i32>,
+    result: UpdateResult,
+}
+
+impl RecordedUpdate {
+    /// Record the update as finished, with the outcome of its pipeline.
+    pub async fn finish(self, status: &StatusRecorder) {
+        if let Some(id) = self.id {
+            let finished_at = time::OffsetDateTime::now_utc().unix_timestamp();
+            status
+                .modify_update(id, |u| u.finish(finished_at, self.exit_code, self.result))
+                .await;
+            tracing::debug!(id, "Update recorded");
+        }
+    }
+}
+
+/// Run every step of `pipeline` with its output redirected to files in the
+/// update directory, and record the outcome as the latest update.
+///
+/// The steps run until one fails, unless it is allowed to. Every step runs
+/// in its own process group, which is terminated as a whole if the step is
+/// still running after its timeout, or `timeout` if it has none. Once every
+/// step has succeeded, the update only succeeds if `verify` does too.
+///
+/// The update stays unfinished in the update history until
+/// [`RecordedUpdate::finish`] is called.
+pub async fn run_pipeline(
+    pipeline: Pipeline<'_>,
+    context: &UpdateContext,
+    timeout: Option<Duration>,
+    status: &StatusRecorder,
+    verify: impl FnOnce() -> Result<(), PollError>,
+) -> (RecordedUpdate, Result<(), PollError>) {
+    let path = &context.update_dir;
+
+    let written = std::fs::write(
+        path.join("context.json"),
+        serde_json::to_vec_pretty(context).expect("failed to serialize context"),
+    )
+    .map_err(PollError::File);
+    if let Err(error) = written {
+        let update = RecordedUpdate {
+            id: None,
+            exit_code: None,
+            result: UpdateResult::Failed,
+        };
+        return (update, Err(error));
+    }
+
+    let started_at = time::OffsetDateTime::now_utc().unix_timestamp();
+
+    let update_id = status
+        .start_update(Update::V1(v1::Update {
+            directory: status.key().to_string(),
+            old_commit: context.old_commit.clone(),
+            new_commit: context.new_commit.clone(),
+            branch: context.branch.clone(),
+            tag: context.tag.clone(),
+            started_at,
+            finished_at: None,
+            exit_code: None,
+            result: None,
+            update_dir: path.display().to_string(),
+            steps: Vec::new(),
+            manual: context.manual,
+        }))
+        .await;
+
+    let mut result = Ok(());
+    let mut exit_code = None;
+
+    for step in pipeline.steps(path, timeout) {
+        let (step_exit_code, step_result) =
+            run_recorded_step(&step, context, status, update_id).await;
+        exit_code = step_exit_code;
+
+        if let Err(error) = step_result {
+            if step.continue_on_error {
+                tracing::warn!(
+                    ?error,
+                    step = step.name,
+                    "Step failed. Continuing with the next step."
+                );
+            } else {
+                result = Err(error);
+                break;
+            }
+        }
+    }
+
+    if result.is_ok() {
+        result = verify();
+    }
+
+    let update_result = match &result {
+        Ok(()) => UpdateResult::Succeeded,
+        Err(_) => UpdateResult::Failed,
+    };
+    status
+        .update(|s| {
+            s.set_last_update(started_at, update_result);
+            if update_result == UpdateResult::Succeeded {
+                s.set_last_successful_update(
+                    path.display().to_string(),
+                    context.reference(),
+                    context.new_commit.clone(),
+                );
+            }
+        })
+        .await;
+
+    if result.is_ok() {
+        let path = path.display();
+        tracing::info!(
+            %path,
+            "Process completed successfully"
+        );
+    }
+
+    let update = RecordedUpdate {
+        id: update_id,
+        exit_code,
+        result: update_result,
+    };
+    (update, result)
+}
+
+/// Run `hook` with `good_commit` as argument after a failed update, and
+/// record it as a step of `update`.
+pub async fn run_failure_hook(
+    hook: &HookCommand,
+    shell: Vec<&str>,
+    context: &UpdateContext,
+    timeout: Option<Duration>,
+    status: &StatusRecorder,
+    update: &RecordedUpdate,
+) -> Result<(), PollError> {
+    let args: Vec<&str> = context.good_commit.as_deref().into_iter().collect();
+    let output_dir = context.update_dir.join("on_failure");
+    let step = hook_step("on_failure", hook, &shell, &args, output_dir, timeout);
+
+    run_recorded_step(&step, context, status, update.id).await.1
+}
+
+/// Run `step`, and record it as a step of the update with the given ID.
+///
+/// Returns the exit code of the step along with the result.
+async fn run_recorded_step(
+    step: &Step<'_>,
+    context: &UpdateContext,
+    status: &StatusRecorder,
+    update_id: Option<u64>,
+) -> (Option<i32>, Result<(), PollError>) {
+    let stdout = step.output_dir.join("stdout");
+    let stderr = step.output_dir.join("stderr");
+
+    if let Some(id) = update_id {
+        status
+            .modify_update(id, |u| {
+                u.start_step(v1::Step {
+                    name: step.name.to_string(),
+                    started_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+                    finished_at: None,
+                    exit_code: None,
+                    result: None,
+                    stdout: stdout.display().to_string(),
+                    stderr: stderr.display().to_string(),
+                })
+            })
+            .await;
+    }
+
+    let outcome = run_step(step, context, &stdout, &stderr).await;
+    let exit_code = outcome.as_ref().ok().and_then(ExitStatus::code);
+
+    let result = match outcome {
+        Ok(exit_status) if exit_status.success() => Ok(()),
+        Ok(_) => Err(PollError::NonZeroExit {
+            path: step.output_dir.display().to_string(),
+        }),
+        Err(error) => Err(error),
+    };
+
+    if let Some(id) = update_id {
+        let finished_at = time::OffsetDateTime::now_utc().unix_timestamp();
+        let step_result = match result {
+            Ok(()) => UpdateResult::Succeeded,
+            Err(_) => UpdateResult::Failed,
+        };
+        status
+            .modify_update(id, |u| u.finish_step(finished_at, exit_code, step_result))
+            .await;
+    }
+
+    (exit_code, result)
+}
+
+async fn run_step(
+    step: &Step<'_>,
+    context: &UpdateContext,
+    stdout: &Path,
+    stderr: &Path,
+) -> Result<ExitStatus, PollError> {
+    std::fs::create_dir_all(&step.output_dir).map_err(PollError::Directory)?;
+    let stdout = File::create(stdout).map_err(PollError::File)?;
+    let stderr = File::create(stderr).map_err(PollError::File)?;
+
+    tracing::debug!(step = step.name, "Running `{}`", step.command.display());
+
+    let mut command = Command::new(step.command);
+    command
+        .args(&step.args)
+        .envs(context.env())
+        .stdout(stdout)
+        .stderr(stderr)
+        .process_group(0);
+
+    if let Some(env) = step.env {
+        command.envs(env);
+    }
+    if let Some(working_dir) = step.working_dir {
+        command.current_dir(working_dir);
+    }
+
+    // Waiting does not block the runtime, so the control socket and the
+    // webhook server keep responding while the step runs.
+    let mut child = tokio::process::Command::from(command)
+        .spawn()
+        .map_err(|error| PollError::Spawn {
+            hook: step.command.to_path_buf(),
+            error,
+        })?;
+
+    let result = match step.timeout {
+        Some(timeout) => wait_with_timeout(&mut child, timeout).await,
+        None => child.wait().await.map(Some),
+    };
+
+    match result {
+        Ok(Some(exit_status)) => Ok(exit_status),
+        Ok(None) => Err(PollError::HookTimeout {
+            hook: step.command.to_path_buf(),
+            timeout: step.timeout.unwrap_or_default(),
+        }),
+        Err(error) => Err(PollError::Complete {
+            hook: step.command.to_path_buf(),
+            error,
+        }),
+    }
+}
+
+/// Wait for `child` to exit. If it is still running after `timeout`, its
+/// process group is sent SIGTERM, and then SIGKILL after a grace period, and
+/// `None` is returned.
+async fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+) -> Result<Option<ExitStatus>, io::Error> {
+    // The ID is gone once the child has been waited for.
+    let id = child.id().expect("the child has not been waited for");
+
+    if let Ok(exit_status) = tokio::time::timeout(timeout, child.wait()).await {
+        return exit_status.map(Some);
+    }
+
+    tracing::warn!(
+        "Hook still running after {} second(s). Terminating it.",
+        timeout.as_secs()
+    );
+    send_signal_to_group(id, Signal::Terminate)?;
+
+    let exited = tokio::time::timeout(KILL_GRACE_PERIOD, child.wait())
+        .await
+        .is_ok();
+    if !exited {
+        tracing::warn!("Hook still running after SIGTERM. Killing it.");
+    }
+
+    // Processes started by the hook may still be running after it has exited.
+    // Once it has been waited for, its ID can be reused for another process
+    // group as soon as the group is empty, so the group is only killed if it
+    // still has processes.
+    if !exited || process_group_exists(id)? {
+        match send_signal_to_group(id, Signal::Kill) {
+            Err(error) if error.raw_os_error() != Some(libc::ESRCH) => return Err(error),
+            _ => {}
+        }
+    }
+
+    if !exited {
+        child.wait().await?;
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn context(update_dir: &Path) -> UpdateContext {
+        UpdateContext {
+            old_commit: None,
+            new_commit: "0000000000000000000000000000000000000000".to_string(),
+            branch: Some("main".to_string()),
+            tag: None,
+            good_commit: None,
+            remote_url: None,
+            repo_dir: None,
+            update_dir: update_dir.to_path_buf(),
+            manual: false,
+        }
+    }
+
+    fn script_step<'a>(
+        script: &'a str,
+        output_dir: PathBuf,
+        timeout: Option<Duration>,
+    ) -> Step<'a> {
+        Step {
+            name: "test",
+            command: Path::new("/bin/sh"),
+            args: vec!["-c", script],
+            working_dir: None,
+            env: None,
+            timeout,
+            continue_on_error: false,
+            output_dir,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("outpost-hook-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn runs_steps_with_the_context_in_their_environment() {
+        let dir = temp_dir("env");
+        let step = script_step("echo \"$OUTPOST_BRANCH\"; exit 3", dir.clone(), None);
+        let stdout = dir.join("stdout");
+
+        let exit_status = run_step(&step, &context(&dir), &stdout, &dir.join("stderr"))
+            .await
+            .unwrap();
+
+        assert_eq!(exit_status.code(), Some(3));
+        assert_eq!(std::fs::read_to_string(&stdout).unwrap(), "main\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn terminates_steps_that_time_out() {
+        let dir = temp_dir("timeout");
+        let step = script_step("sleep 30", dir.clone(), Some(Duration::from_millis(200)));
+
+        let started = Instant::now();
+        let result = run_step(
+            &step,
+            &context(&dir),
+            &dir.join("stdout"),
+            &dir.join("stderr"),
+        )
+        .await;
+
+        assert!(matches!(result, Err(PollError::HookTimeout { .. })));
+        assert!(started.elapsed() < KILL_GRACE_PERIOD);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn kills_processes_left_behind_by_steps_that_time_out() {
+        let dir = temp_dir("group");
+        std::fs::create_dir_all(&dir).unwrap();
+        let pid_file = dir.join("pid");
+        // The subshell ignores SIGTERM, and outlives the step.
+        let script = format!(
+            "echo $$ > '{}'; (trap '' TERM; sleep 30) & sleep 30",
+            pid_file.display()
+        );
+        let step = script_step(&script, dir.clone(), Some(Duration::from_millis(200)));
+
+        let result = run_step(
+            &step,
+            &context(&dir),
+            &dir.join("stdout"),
+            &dir.join("stderr"),
+        )
+        .await;
+        assert!(matches!(result, Err(PollError::HookTimeout { .. })));
+
+        let group: u32 = std::fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while process_group_exists(group).unwrap() {
+            assert!(
+                Instant::now() < deadline,
+                "the process group is still running"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
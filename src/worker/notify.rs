@@ -0,0 +1,216 @@
+use std::{io, process::Stdio, time::Duration};
+
+use serde::Serialize;
+use serde_json::json;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use super::{
+    hook::{hook_argv, UpdateContext},
+    poll::PollError,
+    status::StatusRecorder,
+};
+use crate::config::{Config, HookCommand, NotifyEvent};
+
+/// How long a webhook or the notification command may take.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What is sent for an event, as JSON to webhooks and to the command.
+#[derive(Debug, Serialize)]
+struct Notification<'a> {
+    event: NotifyEvent,
+    /// The directory of the worker.
+    directory: &'a str,
+    timestamp: i64,
+    /// `None` for `stopped`.
+    #[serde(flatten)]
+    update: Option<&'a UpdateContext>,
+    error: Option<String>,
+    /// The last lines of the output of the last step that ran.
+    log_tail: Option<String>,
+}
+
+/// Send `event` about the update of `context`, if it is configured.
+pub async fn notify_update(
+    config: &Config,
+    event: NotifyEvent,
+    context: &UpdateContext,
+    error: Option<&PollError>,
+    status: &StatusRecorder,
+) {
+    let Some(notify) = &config.notify else {
+        return;
+    };
+    if !notify.events.contains(&event) {
+        return;
+    }
+
+    let log_tail = if event == NotifyEvent::Started {
+        None
+    } else {
+        log_tail(status, notify.log_lines).await
+    };
+
+    send(
+        config,
+        &Notification {
+            event,
+            directory: status.key(),
+            timestamp: time::OffsetDateTime::now_utc().unix_timestamp(),
+            update: Some(context),
+            error: error.map(|error| format!("{error:?}")),
+            log_tail,
+        },
+    )
+    .await;
+}
+
+/// Send `stopped`, if it is configured.
+pub async fn notify_stopped(config: &Config, error: Option<&PollError>, status: &StatusRecorder) {
+    let Some(notify) = &config.notify else {
+        return;
+    };
+    if !notify.events.contains(&NotifyEvent::Stopped) {
+        return;
+    }
+
+    send(
+        config,
+        &Notification {
+            event: NotifyEvent::Stopped,
+            directory: status.key(),
+            timestamp: time::OffsetDateTime::now_utc().unix_timestamp(),
+            update: None,
+            error: error.map(|error| format!("{error:?}")),
+            log_tail: None,
+        },
+    )
+    .await;
+}
+
+async fn send(config: &Config, notification: &Notification<'_>) {
+    let Some(notify) = &config.notify else {
+        return;
+    };
+
+    tracing::debug!(event = ?notification.event, "Sending notifications.");
+
+    let client = match reqwest::Client::builder().timeout(NOTIFY_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(error) => {
+            tracing::warn!(?error, "Failed to create the HTTP client for notifications");
+            return;
+        }
+    };
+
+    let slack = json!({ "text": summary(notification) });
+    let requests = notify
+        .webhooks
+        .iter()
+        .map(|url| (url, client.post(url).json(notification)))
+        .chain(
+            notify
+                .slack
+                .iter()
+                .map(|url| (url, client.post(url).json(&slack))),
+        );
+
+    for (url, request) in requests {
+        match request.send().await.and_then(|r| r.error_for_status()) {
+            Ok(_) => {}
+            Err(error) => tracing::warn!(?error, "Failed to send a notification to `{url}`"),
+        }
+    }
+
+    if let Some(command) = &notify.command {
+        if let Err(error) = run_command(config, command, notification).await {
+            tracing::warn!(?error, "The notification command failed");
+        }
+    }
+}
+
+async fn run_command(
+    config: &Config,
+    command: &HookCommand,
+    notification: &Notification<'_>,
+) -> Result<(), io::Error> {
+    let shell = config.shell_program();
+    let (program, args) = hook_argv(command, &shell);
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let input = serde_json::to_vec(notification).expect("failed to serialize notification");
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&input).await?;
+    }
+
+    let exit_status = match tokio::time::timeout(NOTIFY_TIMEOUT, child.wait()).await {
+        Ok(exit_status) => exit_status?,
+        Err(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "did not complete in time",
+            ))
+        }
+    };
+
+    if exit_status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("exited with {exit_status}"),
+        ))
+    }
+}
+
+/// A single line, followed by the log tail, for chat services.
+fn summary(notification: &Notification) -> String {
+    let directory = notification.directory;
+    let mut summary = match notification.update {
+        Some(update) => {
+            let target = update
+                .branch
+                .as_deref()
+                .or(update.tag.as_deref())
+                .unwrap_or("HEAD");
+            let commit = &update.new_commit[..update.new_commit.len().min(7)];
+            let outcome = match notification.event {
+                NotifyEvent::Started => "started",
+                NotifyEvent::Succeeded => "succeeded",
+                NotifyEvent::Failed => "failed",
+                NotifyEvent::Stopped => "stopped",
+            };
+            format!("Update of `{target}` to `{commit}` in `{directory}` {outcome}.")
+        }
+        None => format!("The worker in `{directory}` stopped."),
+    };
+
+    if let Some(error) = &notification.error {
+        summary.push_str(&format!("\nError: `{error}`"));
+    }
+    if let Some(log_tail) = notification.log_tail.as_deref().filter(|t| !t.is_empty()) {
+        summary.push_str(&format!("\n```\n{log_tail}\n```"));
+    }
+
+    summary
+}
+
+/// The last `lines` lines of the stderr of the last step of the latest update,
+/// or of its stdout if nothing was written to stderr.
+async fn log_tail(status: &StatusRecorder, lines: usize) -> Option<String> {
+    let update = status.read_update(status.last_update_id()?).await?;
+    let step = update.steps().last()?;
+
+    let output = [&step.stderr, &step.stdout]
+        .into_iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .find(|output| !output.trim().is_empty())?;
+
+    let tail: Vec<&str> = output.lines().rev().take(lines).collect();
+    Some(tail.into_iter().rev().collect::<Vec<_>>().join("\n"))
+}
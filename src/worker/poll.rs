@@ -1,29 +1,96 @@
-pub use crate::fetch_and_compare::{fetch_and_compare, FetchError, FetchResult};
+use super::{
+    control::{self, Command as ControlCommand, Control},
+    failure::{FailureTracker, Recovery},
+    hook::{
+        create_update_directory, run_failure_hook, run_pipeline, Pipeline, RecordedUpdate,
+        UpdateContext,
+    },
+    notify::{notify_stopped, notify_update},
+    retention::prune,
+    status::StatusRecorder,
+    webhook,
+};
+pub use crate::fetch_and_compare::{
+    fetch_and_compare, fetch_and_compare_branches, fetch_and_compare_tags, FetchError, FetchResult,
+};
 use crate::{
-    config::Credentials,
-    git::{GitError, Repository},
+    config::{
+        Config, ConfigError, Credentials, HookCommand, NotifyEvent, TagConfig, UpdateStrategy,
+        WatchConfig,
+    },
+    database::SeenRef,
+    git::{Branch, GitError, RemoteRepository, Repository},
 };
 use gix::ObjectId;
-use std::{fs::File, io, path::PathBuf, process::Command, time::Duration};
-use time::macros::format_description;
-use tokio::runtime::Runtime;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    runtime::Runtime,
+    signal::unix::{signal, SignalKind},
+    sync::Notify,
+};
+
+const DEFAULT_INTERVAL: u64 = 60;
 
 #[derive(Debug)]
 pub enum PollError {
+    Config(ConfigError),
+    CurrentDirectory(io::Error),
+    HomeDirectoryMissing,
     Git(GitError),
     Fetch(FetchError),
     Directory(io::Error),
     File(io::Error),
-    Spawn(io::Error),
-    Complete(io::Error),
+    Spawn {
+        hook: PathBuf,
+        error: io::Error,
+    },
+    Complete {
+        hook: PathBuf,
+        error: io::Error,
+    },
+    Signal(io::Error),
+    /// `listen` mode was requested, but the configuration has no `[listen]`
+    /// section.
+    ListenMissing,
+    Listen(hyper::Error),
+    ControlSocket(io::Error),
+    HookTimeout {
+        hook: PathBuf,
+        timeout: Duration,
+    },
     NonZeroExit {
         path: String,
     },
     BranchWasNotUpdated,
+    NotFastForward {
+        current_commit_id: ObjectId,
+        remote_commit_id: ObjectId,
+    },
     UnexpectedCommitId {
         remote_commit_id: ObjectId,
         updated_commit_id: ObjectId,
     },
+    /// The updates of several branches or tags failed in the same check.
+    UpdatesFailed(Vec<PollError>),
+}
+
+impl PollError {
+    /// `Ok` without errors, the error itself if there is only one, or else
+    /// all of them together.
+    fn collect(mut errors: Vec<PollError>) -> Result<(), PollError> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(PollError::UpdatesFailed(errors)),
+        }
+    }
 }
 
 impl From<GitError> for PollError {
@@ -38,116 +105,220 @@ impl From<FetchError> for PollError {
     }
 }
 
-pub fn poll(
-    on_update: PathBuf,
-    updates: PathBuf,
-    interval: Duration,
-    iterations: Option<usize>,
-    credentials: Option<Credentials>,
-) -> Result<(), PollError> {
-    let repo = Repository::discover()?;
+pub fn poll(config_path: PathBuf) -> Result<(), PollError> {
+    run(config_path, false)
+}
 
-    let current_branch = repo.current_branch()?;
+/// Like [`poll`], but also receive webhooks as configured in `[listen]`, and
+/// check as soon as a push is received.
+pub fn listen(config_path: PathBuf) -> Result<(), PollError> {
+    run(config_path, true)
+}
 
-    tracing::debug!(
-        "Branch detected: {}",
-        current_branch.as_reference().full_name()
-    );
+fn run(config_path: PathBuf, listen: bool) -> Result<(), PollError> {
+    let mut config = Config::from_path(&config_path).map_err(PollError::Config)?;
 
-    let remote_branch = repo.remote_branch(&current_branch)?;
+    let key = std::env::current_dir()
+        .map_err(PollError::CurrentDirectory)?
+        .display()
+        .to_string();
 
-    tracing::debug!(
-        "Local branch `{}` tracks remote branch `{}`.",
-        current_branch.as_reference().full_name(),
-        remote_branch.as_reference().full_name(),
-    );
+    let status = StatusRecorder::new(key);
+
+    // Read by gix whenever it connects to an SSH remote. Set before the
+    // runtime starts any threads, so changes to `[ssh]` only take effect when
+    // the worker is restarted.
+    if let Some(ssh) = &config.ssh {
+        ssh.apply();
+    }
+
+    // Changes to `remote`, `branch` and `watch` only take effect when the
+    // worker is restarted.
+    let source = match &config.watch {
+        Some(watch) => Source::Remote(open_remote(watch)?),
+        None => Source::Checkout(Repository::discover()?.with_remote(config.remote.clone())),
+    };
+
+    let mut credentials = resolve_credentials(&config, &source).map_err(PollError::Config)?;
+
+    // Only needed when following a single branch.
+    let mut remote_branch = match &source {
+        Source::Checkout(repo) if config.follows_head() => Some(tracked_branch(repo, &config)?),
+        _ => None,
+    };
 
     let runtime = Runtime::new().unwrap();
+
+    let recorded = runtime.block_on(status.read());
+    let mut seen = Seen {
+        branches: recorded.seen_branches().map(|branches| {
+            branches
+                .iter()
+                .filter_map(|(name, id)| {
+                    Some((name.clone(), ObjectId::from_hex(id.as_bytes()).ok()?))
+                })
+                .collect()
+        }),
+        tags: recorded.seen_tags().cloned(),
+        remote: config.watch.as_ref().and_then(|watch| {
+            let seen = recorded.seen_ref()?;
+            (seen.url == watch.url && seen.reference == watch.full_name())
+                .then(|| ObjectId::from_hex(seen.id.as_bytes()).ok())?
+        }),
+    };
+    let mut failures = runtime.block_on(FailureTracker::new(&status));
+
+    let iterations = config.iterations;
+
     let future = async {
-        if !updates.exists() {
-            std::fs::create_dir(&updates).map_err(PollError::Directory)?;
+        if !config.updates.exists() {
+            std::fs::create_dir(&config.updates).map_err(PollError::Directory)?;
+        }
+
+        // Registering the handler replaces the default action, so a SIGTERM
+        // that arrives while the hook is running is only observed once the
+        // hook has completed.
+        let mut terminate = signal(SignalKind::terminate()).map_err(PollError::Signal)?;
+        let mut hangup = signal(SignalKind::hangup()).map_err(PollError::Signal)?;
+
+        // Changes to `[listen]` only take effect when the worker is restarted.
+        let wake = Arc::new(Notify::new());
+        if listen {
+            let listen_config = config.listen.as_ref().ok_or(PollError::ListenMissing)?;
+            webhook::serve(listen_config, wake.clone())?;
         }
 
-        let mut current_commit_id;
+        let (control, mut commands) = Control::new(status.key().to_string(), wake.clone());
+        let _socket = control::serve(control.clone())?;
+        control.set_force_supported(config.supports_force());
+
+        let mut paused = false;
+
         // TODO: use actual `loop` when `iterations` is not set
-        for _ in 1..iterations.unwrap_or(usize::MAX) {
-            current_commit_id = repo.current_commit_id()?;
-            match fetch_and_compare(
-                &repo,
-                &remote_branch,
-                current_commit_id,
+        'checks: for _ in 1..iterations.unwrap_or(usize::MAX) {
+            control.checking();
+            let force = control.take_force();
+
+            let result = check(
+                &source,
+                &mut remote_branch,
+                &config,
+                &status,
+                &mut seen,
                 credentials.as_ref(),
+                force,
             )
-            .await?
-            {
-                FetchResult::UpToDate { .. } => {
-                    tracing::info!("Up to date.");
-                }
-                FetchResult::OutOfDate {
-                    remote_commit_id, ..
-                } => {
-                    tracing::info!("Update found.");
-
-                    let format =
-                        format_description!("[year]-[month]-[day]_[hour]-[minute]-[second]");
-                    let name = time::OffsetDateTime::now_utc()
-                        .format(format)
-                        .expect("invalid format");
-
-                    let path = updates.join(name);
-
-                    tracing::debug!("Creating `{}`", path.display());
-
-                    std::fs::create_dir(&path).map_err(PollError::Directory)?;
-                    let stdout = File::create(path.join("stdout")).map_err(PollError::File)?;
-                    let stderr = File::create(path.join("stderr")).map_err(PollError::File)?;
-
-                    tracing::debug!("Running `{}`", &on_update.display());
-
-                    let output = Command::new(&on_update)
-                        .stdout(stdout)
-                        .stderr(stderr)
-                        .spawn()
-                        .map_err(PollError::Spawn)?
-                        .wait_with_output()
-                        .map_err(PollError::Complete)?;
-
-                    if output.status.success() {
-                        let path = path.display();
-                        tracing::info!(
-                            %path,
-                            "Process completed successfully"
-                        );
-                    } else {
-                        return Err(PollError::NonZeroExit {
-                            path: path.display().to_string(),
-                        });
-                    }
+            .await;
 
-                    let updated_commit_id = repo.current_commit_id()?;
+            // Hooks may have run before a check failed.
+            if config.retention.is_enabled() && !matches!(result, Ok(false)) {
+                let keep = status
+                    .read()
+                    .await
+                    .last_successful_update()
+                    .map(PathBuf::from);
+                prune(&config.updates, &config.retention, keep.as_deref());
+            }
 
-                    if current_commit_id == updated_commit_id {
-                        return Err(PollError::BranchWasNotUpdated);
-                    }
-                    if remote_commit_id != updated_commit_id {
-                        return Err(PollError::UnexpectedCommitId {
-                            remote_commit_id,
-                            updated_commit_id,
+            let mut delay = Duration::from_secs(config.interval.unwrap_or(DEFAULT_INTERVAL));
+
+            match result {
+                Ok(_) => failures.succeeded(&status).await,
+                Err(error) => match failures.failed(&config.failure, error, &status).await? {
+                    Recovery::Retry(backoff) => delay = backoff,
+                    Recovery::Continue => {}
+                    Recovery::Pause => paused = true,
+                },
+            }
+
+            let sleep = tokio::time::sleep(delay);
+            tokio::pin!(sleep);
+
+            control.waiting(
+                Some(delay).filter(|_| !paused),
+                failures.consecutive_failures(),
+            );
+
+            // TODO: should not sleep on the last iteration
+            loop {
+                tokio::select! {
+                    _ = &mut sleep, if !paused => break,
+                    _ = wake.notified(), if !paused => break,
+                    _ = hangup.recv() => {
+                        // Reloading skips the rest of the current interval.
+                        let reloaded = Config::from_path(&config_path).and_then(|reloaded| {
+                            let reloaded_credentials =
+                                resolve_credentials(&reloaded, &source)?;
+                            Ok((reloaded, reloaded_credentials))
                         });
+                        match reloaded {
+                            Ok((reloaded, reloaded_credentials)) => {
+                                tracing::info!("Received SIGHUP. Configuration reloaded.");
+                                config = reloaded;
+                                credentials = reloaded_credentials;
+                                control.set_force_supported(config.supports_force());
+                            }
+                            Err(error) => {
+                                tracing::error!(
+                                    ?error,
+                                    "Received SIGHUP, but `{}` could not be read. Keeping the current configuration.",
+                                    config_path.display()
+                                );
+                            }
+                        }
+                        if paused {
+                            tracing::info!("Resuming.");
+                            paused = false;
+                            failures.resumed(&status).await;
+                        }
+                        break;
+                    }
+                    Some(command) = commands.recv() => match command {
+                        ControlCommand::Pause => {
+                            tracing::info!("Pausing on request.");
+                            paused = true;
+                            control.waiting(None, failures.consecutive_failures());
+                        }
+                        // Resuming checks right away.
+                        ControlCommand::Resume if paused => {
+                            tracing::info!("Resuming on request.");
+                            paused = false;
+                            failures.resumed(&status).await;
+                            break;
+                        }
+                        ControlCommand::Resume => {}
+                        ControlCommand::Shutdown => {
+                            tracing::info!("Shutdown requested. Stopping.");
+                            break 'checks;
+                        }
+                    },
+                    _ = terminate.recv() => {
+                        tracing::info!("Received SIGTERM. Stopping.");
+                        break 'checks;
                     }
                 }
-            };
-            // TODO: should not sleep on the last iteration
-            tokio::time::sleep(interval).await;
+            }
         }
 
         Ok(())
     };
 
-    match runtime.block_on(future) {
+    let result = runtime.block_on(future);
+    runtime.block_on(notify_stopped(&config, result.as_ref().err(), &status));
+
+    match result {
         Ok(()) => {
             tracing::info!("Polling finished.");
         }
+        Err(PollError::Config(error)) => {
+            tracing::error!(?error, "Failed to read `{}`", config_path.display());
+        }
+        Err(PollError::CurrentDirectory(error)) => {
+            tracing::error!(?error, "Failed to find the current directory");
+        }
+        Err(PollError::HomeDirectoryMissing) => {
+            tracing::error!("Failed to find the home directory");
+        }
         Err(PollError::Git(error)) => {
             tracing::error!(
                 ?error,
@@ -163,22 +334,54 @@ pub fn poll(
         Err(PollError::Fetch(error)) => {
             tracing::error!(?error, "Failed to fetch changes from the remote repository");
         }
-        Err(PollError::Spawn(error)) => {
-            tracing::error!(?error, "Failed to execute `{}`", on_update.display());
+        Err(PollError::Spawn { hook, error }) => {
+            tracing::error!(?error, "Failed to execute `{}`", hook.display());
         }
-        Err(PollError::Complete(error)) => {
+        Err(PollError::Complete { hook, error }) => {
             tracing::error!(
                 ?error,
                 "Failed to complete the command `{}`",
-                on_update.display(),
+                hook.display(),
             )
         }
+        Err(PollError::Signal(error)) => {
+            tracing::error!(?error, "Failed to register signal handler");
+        }
+        Err(PollError::ListenMissing) => {
+            tracing::error!(
+                "`{}` has no `[listen]` section to receive webhooks",
+                config_path.display()
+            );
+        }
+        Err(PollError::Listen(error)) => {
+            tracing::error!(?error, "Failed to start the webhook server");
+        }
+        Err(PollError::ControlSocket(error)) => {
+            tracing::error!(?error, "Failed to set up the control socket");
+        }
+        Err(PollError::HookTimeout { hook, timeout }) => {
+            tracing::error!(
+                "`{}` did not complete within {} second(s) and was terminated",
+                hook.display(),
+                timeout.as_secs()
+            );
+        }
         Err(PollError::NonZeroExit { path }) => {
             tracing::error!(%path, "Process exited with a non-zero exit code");
         }
         Err(PollError::BranchWasNotUpdated) => {
             tracing::error!("The current branch has not been updated. Exiting the process.");
         }
+        Err(PollError::NotFastForward {
+            current_commit_id,
+            remote_commit_id,
+        }) => {
+            tracing::error!(
+                %current_commit_id,
+                %remote_commit_id,
+                "The current branch cannot be fast-forwarded to the remote commit. Exiting the process."
+            );
+        }
         Err(PollError::UnexpectedCommitId {
             remote_commit_id,
             updated_commit_id,
@@ -188,7 +391,661 @@ pub fn poll(
                 %updated_commit_id,
                 "The current branch has been updated, but it is not the expected commit. Exiting the process.");
         }
+        Err(PollError::UpdatesFailed(errors)) => {
+            tracing::error!(?errors, "The updates of {} references failed", errors.len());
+        }
+    }
+
+    Ok(())
+}
+
+/// Where updates come from.
+enum Source {
+    /// The repository that the worker runs in.
+    Checkout(Repository),
+    /// A remote repository that is watched without a checkout.
+    Remote(RemoteRepository),
+}
+
+impl Source {
+    /// The host that is fetched from, if the URL has one.
+    fn host(&self) -> Result<Option<String>, GitError> {
+        match self {
+            Self::Checkout(repo) => repo.remote_host(),
+            Self::Remote(remote) => remote.host(),
+        }
+    }
+}
+
+/// What has been seen on the remote, so that hooks only run for changes.
+///
+/// Each is `None` until the remote is first fetched.
+struct Seen {
+    branches: Option<BTreeMap<String, ObjectId>>,
+    tags: Option<BTreeSet<String>>,
+    /// The object of the watched reference of a remote repository.
+    remote: Option<ObjectId>,
+}
+
+/// Run every check that the configuration asks for once, and return whether
+/// any hook was run.
+///
+/// With `force`, the update of the followed branch, of every watched branch
+/// and of the watched reference of a remote repository is run even if it has
+/// not changed. Tags are not affected.
+async fn check(
+    source: &Source,
+    remote_branch: &mut Option<Branch>,
+    config: &Config,
+    status: &StatusRecorder,
+    seen: &mut Seen,
+    credentials: Option<&Credentials>,
+    force: bool,
+) -> Result<bool, PollError> {
+    let repo = match (source, &config.watch) {
+        (Source::Checkout(repo), _) => repo,
+        (Source::Remote(remote), Some(watch)) => {
+            return check_remote(
+                remote,
+                config,
+                watch,
+                status,
+                &mut seen.remote,
+                credentials,
+                force,
+            )
+            .await;
+        }
+        (Source::Remote(_), None) => {
+            tracing::warn!("`watch` has been removed. Restart the worker to apply this.");
+            return Ok(false);
+        }
+    };
+
+    let mut updated = false;
+
+    if config.follows_head() {
+        let remote_branch = match remote_branch {
+            Some(branch) => branch,
+            None => remote_branch.insert(tracked_branch(repo, config)?),
+        };
+        updated |=
+            check_current_branch(repo, remote_branch, config, status, credentials, force).await?;
+    }
+
+    // A failure of the branches does not keep the tags from being checked.
+    let mut errors = Vec::new();
+    if !config.branches.is_empty() {
+        match check_branches(repo, config, status, &mut seen.branches, credentials, force).await {
+            Ok(branches_updated) => updated |= branches_updated,
+            Err(error) => errors.push(error),
+        }
+    }
+    if let Some(tags) = &config.tags {
+        match check_tags(repo, config, tags, status, &mut seen.tags, credentials).await {
+            Ok(tags_updated) => updated |= tags_updated,
+            Err(error) => errors.push(error),
+        }
+    }
+    PollError::collect(errors)?;
+
+    Ok(updated)
+}
+
+/// Open the remote repository of `watch`, using a scratch repository in the
+/// outpost directory that is named after the URL.
+fn open_remote(watch: &WatchConfig) -> Result<RemoteRepository, PollError> {
+    let remotes_dir = home::home_dir()
+        .ok_or(PollError::HomeDirectoryMissing)?
+        .join(".outpost")
+        .join("remotes");
+
+    let hash = Sha256::digest(watch.url.as_bytes());
+    let scratch = remotes_dir.join(hex::encode(&hash[..16]));
+
+    Ok(RemoteRepository::open(&scratch, watch.url.clone())?)
+}
+
+/// Run the hook if the watched reference of a remote repository points to
+/// another object than when it was last seen.
+///
+/// The first time the remote is seen, the object is recorded without running
+/// any hooks.
+async fn check_remote(
+    remote: &RemoteRepository,
+    config: &Config,
+    watch: &WatchConfig,
+    status: &StatusRecorder,
+    seen: &mut Option<ObjectId>,
+    credentials: Option<&Credentials>,
+    force: bool,
+) -> Result<bool, PollError> {
+    let full_name = watch.full_name();
+
+    let id = remote
+        .ls_remote(&full_name, credentials)
+        .map_err(FetchError::from)?
+        .ok_or(FetchError::FetchRemoteMissing)?;
+
+    let previous_id = match *seen {
+        Some(previous_id) => previous_id,
+        None => {
+            tracing::info!("Recording `{full_name}` at {id}.");
+            save_seen_ref(status, remote, &full_name, id).await;
+            *seen = Some(id);
+            return Ok(false);
+        }
+    };
+
+    let manual = if previous_id != id {
+        tracing::info!(previous_id = %previous_id, id = %id, "Update found.");
+        false
+    } else if force {
+        tracing::info!("Up to date. Running the update anyway, as it was forced.");
+        true
+    } else {
+        tracing::info!("Up to date.");
+        return Ok(false);
+    };
+
+    let path = create_update_directory(&config.updates, Some(&watch.reference))?;
+    let mut context = UpdateContext {
+        old_commit: Some(previous_id.to_string()),
+        branch: full_name.strip_prefix("refs/heads/").map(String::from),
+        tag: full_name.strip_prefix("refs/tags/").map(String::from),
+        manual,
+        ..UpdateContext::for_remote(remote.url(), path, id)
+    };
+    context.good_commit = good_commit(status, &context).await;
+    run_update(config, pipeline(config, None, &[]), &context, status, None).await?;
+
+    save_seen_ref(status, remote, &full_name, id).await;
+    *seen = Some(id);
+
+    Ok(true)
+}
+
+/// The credentials from the file in `config`. `None` leaves it to the
+/// configured credential helpers.
+fn resolve_credentials(
+    config: &Config,
+    source: &Source,
+) -> Result<Option<Credentials>, ConfigError> {
+    let Some(credentials) = &config.credentials else {
+        return Ok(None);
+    };
+
+    // Only a `.netrc` file has entries per host. Without a host, its
+    // `default` entry is used.
+    let host = if credentials.is_netrc() {
+        source.host().unwrap_or_else(|error| {
+            tracing::warn!(
+                ?error,
+                "Failed to find the host of the remote. Using the `default` entry."
+            );
+            None
+        })
+    } else {
+        None
+    };
+
+    credentials.load(host.as_deref()).map(Some)
+}
+
+/// The remote branch to follow: `branch` if configured, or else the one
+/// tracked by the branch that HEAD points to.
+fn tracked_branch(repo: &Repository, config: &Config) -> Result<Branch, GitError> {
+    if let Some(branch) = &config.branch {
+        tracing::debug!("Following remote branch `{branch}`.");
+        return Ok(Branch::Remote(branch.clone()));
+    }
+
+    let current_branch = repo.current_branch()?;
+
+    tracing::debug!(
+        "Branch detected: {}",
+        current_branch.as_reference().full_name()
+    );
+
+    let remote_branch = repo.remote_branch(&current_branch)?;
+
+    tracing::debug!(
+        "Local branch `{}` tracks remote branch `{}`.",
+        current_branch.as_reference().full_name(),
+        remote_branch.as_reference().full_name(),
+    );
+
+    Ok(remote_branch)
+}
+
+/// Run the hook if HEAD is behind the followed remote branch, and verify that
+/// HEAD has been updated.
+///
+/// Depending on `update_strategy`, the branch is updated either by the hook
+/// or by outpost before the hook runs.
+async fn check_current_branch(
+    repo: &Repository,
+    remote_branch: &Branch,
+    config: &Config,
+    status: &StatusRecorder,
+    credentials: Option<&Credentials>,
+    force: bool,
+) -> Result<bool, PollError> {
+    let strategy = config.update_strategy;
+    let dry_run = strategy == UpdateStrategy::Hook;
+
+    let current_commit_id = repo.current_commit_id()?;
+    match fetch_and_compare(repo, remote_branch, current_commit_id, dry_run, credentials).await? {
+        FetchResult::UpToDate if force => {
+            tracing::info!("Up to date. Running the update anyway, as it was forced.");
+
+            let path = create_update_directory(&config.updates, None)?;
+            let mut context = UpdateContext {
+                old_commit: Some(current_commit_id.to_string()),
+                branch: Some(remote_branch.short_name().to_string()),
+                manual: true,
+                ..UpdateContext::new(repo, path, current_commit_id)
+            };
+            context.good_commit = good_commit(status, &context).await;
+            run_update(
+                config,
+                pipeline(config, None, &[]),
+                &context,
+                status,
+                config.rollback.then_some(repo),
+            )
+            .await?;
+
+            Ok(true)
+        }
+        FetchResult::UpToDate => {
+            tracing::info!("Up to date.");
+            Ok(false)
+        }
+        FetchResult::OutOfDate { remote_commit_id } => {
+            tracing::info!("Update found.");
+
+            if strategy == UpdateStrategy::FastForward
+                && !repo.is_ancestor(current_commit_id, remote_commit_id)?
+            {
+                return Err(PollError::NotFastForward {
+                    current_commit_id,
+                    remote_commit_id,
+                });
+            }
+            if strategy != UpdateStrategy::Hook {
+                let local_branch = repo.head_branch()?;
+                repo.checkout(local_branch.as_ref(), current_commit_id, remote_commit_id)?;
+                tracing::info!(
+                    "Checked out `{}` at {remote_commit_id}.",
+                    local_branch.as_ref().map_or("HEAD", Branch::short_name)
+                );
+            }
+
+            let path = create_update_directory(&config.updates, None)?;
+            let mut context = UpdateContext {
+                old_commit: Some(current_commit_id.to_string()),
+                branch: Some(remote_branch.short_name().to_string()),
+                ..UpdateContext::new(repo, path, remote_commit_id)
+            };
+            context.good_commit = good_commit(status, &context).await;
+            // Checked before the update is recorded as successful, so that a
+            // hook that did not update the branch neither becomes the target
+            // of a rollback nor is reported as a success.
+            let verify = || {
+                let updated_commit_id = repo.current_commit_id()?;
+
+                if strategy == UpdateStrategy::Hook && current_commit_id == updated_commit_id {
+                    return Err(PollError::BranchWasNotUpdated);
+                }
+                if remote_commit_id != updated_commit_id {
+                    return Err(PollError::UnexpectedCommitId {
+                        remote_commit_id,
+                        updated_commit_id,
+                    });
+                }
+
+                Ok(())
+            };
+            run_verified_update(
+                config,
+                pipeline(config, None, &[]),
+                &context,
+                status,
+                config.rollback.then_some(repo),
+                verify,
+            )
+            .await?;
+
+            Ok(true)
+        }
     }
+}
+
+/// Run the configured hook for every watched remote branch whose tip has
+/// changed since it was last seen.
+///
+/// The first time the remote is seen, the current tips are recorded without
+/// running any hooks.
+///
+/// A branch is recorded as seen whether or not its update succeeded, so that
+/// a failing hook is not run again until the branch changes, and the other
+/// branches are updated regardless. The errors are returned together.
+async fn check_branches(
+    repo: &Repository,
+    config: &Config,
+    status: &StatusRecorder,
+    seen_branches: &mut Option<BTreeMap<String, ObjectId>>,
+    credentials: Option<&Credentials>,
+    force: bool,
+) -> Result<bool, PollError> {
+    let patterns: Vec<_> = config.branches.iter().map(|b| b.pattern.clone()).collect();
+    let is_first_fetch = seen_branches.is_none();
+    let seen = seen_branches.get_or_insert_with(BTreeMap::new);
+
+    let updates = fetch_and_compare_branches(repo, &patterns, seen, credentials).await?;
+
+    if is_first_fetch {
+        tracing::info!(
+            "Recording the current state of {} branch(es).",
+            updates.len()
+        );
+        seen.extend(updates.into_iter().map(|update| (update.name, update.id)));
+        save_seen_branches(status, seen).await;
+        return Ok(false);
+    }
+
+    // Every branch that has not changed, at the commit it was last seen at.
+    let forced: Vec<_> = if force {
+        seen.iter()
+            .filter(|(name, _)| !updates.iter().any(|update| &update.name == *name))
+            .map(|(name, id)| (name.clone(), *id))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let updated = !updates.is_empty() || !forced.is_empty();
+    if updates.is_empty() {
+        tracing::info!("Up to date.");
+    }
+
+    let mut errors = Vec::new();
+
+    for (name, id) in forced {
+        let branch = name.as_str();
+        tracing::info!(branch, %id, "Running the update, as it was forced.");
+
+        let hook = config.branch(branch).and_then(|b| b.on_update.as_ref());
+
+        let result = async {
+            let path = create_update_directory(&config.updates, Some(branch))?;
+            let mut context = UpdateContext {
+                old_commit: Some(id.to_string()),
+                branch: Some(name.clone()),
+                manual: true,
+                ..UpdateContext::new(repo, path, id)
+            };
+            context.good_commit = good_commit(status, &context).await;
+            run_update(config, pipeline(config, hook, &[]), &context, status, None).await
+        }
+        .await;
+        errors.extend(result.err());
+    }
+
+    for update in updates {
+        let branch = update.name.as_str();
+        tracing::info!(
+            branch,
+            previous_id = ?update.previous_id,
+            id = %update.id,
+            "Update found."
+        );
+
+        let hook = config.branch(branch).and_then(|b| b.on_update.as_ref());
+
+        let result = async {
+            let path = create_update_directory(&config.updates, Some(branch))?;
+            let mut context = UpdateContext {
+                old_commit: update.previous_id.map(|id| id.to_string()),
+                branch: Some(update.name.clone()),
+                ..UpdateContext::new(repo, path, update.id)
+            };
+            context.good_commit = good_commit(status, &context).await;
+            run_update(config, pipeline(config, hook, &[]), &context, status, None).await
+        }
+        .await;
+        errors.extend(result.err());
+
+        seen.insert(update.name, update.id);
+        save_seen_branches(status, seen).await;
+    }
+
+    PollError::collect(errors)?;
+
+    Ok(updated)
+}
+
+/// Run the configured hook, with the name of the tag and the commit it
+/// points to as arguments, for every watched tag that has not been seen.
+/// Configured steps get their own arguments instead.
+///
+/// The first time the remote is seen, the current tags are recorded without
+/// running any hooks.
+///
+/// Like branches, a tag is recorded as seen whether or not its update
+/// succeeded, and the errors are returned together.
+async fn check_tags(
+    repo: &Repository,
+    config: &Config,
+    tags: &TagConfig,
+    status: &StatusRecorder,
+    seen_tags: &mut Option<BTreeSet<String>>,
+    credentials: Option<&Credentials>,
+) -> Result<bool, PollError> {
+    let is_first_fetch = seen_tags.is_none();
+    let seen = seen_tags.get_or_insert_with(BTreeSet::new);
+
+    let updates = fetch_and_compare_tags(repo, tags, seen, credentials).await?;
+
+    if is_first_fetch {
+        tracing::info!("Recording {} existing tag(s).", updates.len());
+        seen.extend(updates.into_iter().map(|update| update.name));
+        status.update(|s| s.set_seen_tags(seen.clone())).await;
+        return Ok(false);
+    }
+
+    let updated = !updates.is_empty();
+    if !updated {
+        tracing::info!("No new tags.");
+    }
+
+    let hook = tags.on_update.as_ref();
+    let mut errors = Vec::new();
+
+    for update in updates {
+        let tag = update.name.as_str();
+        tracing::info!(tag, commit_id = %update.commit_id, "New tag found.");
+
+        let commit_id = update.commit_id.to_string();
+        let result = async {
+            let path = create_update_directory(&config.updates, Some(tag))?;
+            let mut context = UpdateContext {
+                tag: Some(update.name.clone()),
+                ..UpdateContext::new(repo, path, update.commit_id)
+            };
+            context.good_commit = good_commit(status, &context).await;
+            run_update(
+                config,
+                pipeline(config, hook, &[tag, &commit_id]),
+                &context,
+                status,
+                None,
+            )
+            .await
+        }
+        .await;
+        errors.extend(result.err());
+
+        seen.insert(update.name);
+        status.update(|s| s.set_seen_tags(seen.clone())).await;
+    }
+
+    PollError::collect(errors)?;
+
+    Ok(updated)
+}
+
+/// Run `pipeline` for the update described by `context`, and send
+/// notifications about it. If the update fails, it is handled as configured,
+/// and `rollback` is rolled back if given.
+async fn run_update(
+    config: &Config,
+    pipeline: Pipeline<'_>,
+    context: &UpdateContext,
+    status: &StatusRecorder,
+    rollback: Option<&Repository>,
+) -> Result<(), PollError> {
+    run_verified_update(config, pipeline, context, status, rollback, || Ok(())).await
+}
+
+/// Like [`run_update`], but the update only succeeds if `verify` does too,
+/// once every step has succeeded.
+async fn run_verified_update(
+    config: &Config,
+    pipeline: Pipeline<'_>,
+    context: &UpdateContext,
+    status: &StatusRecorder,
+    rollback: Option<&Repository>,
+    verify: impl FnOnce() -> Result<(), PollError>,
+) -> Result<(), PollError> {
+    notify_update(config, NotifyEvent::Started, context, None, status).await;
+
+    let (update, result) =
+        run_pipeline(pipeline, context, config.hook_timeout(), status, verify).await;
+
+    match result {
+        Ok(()) => {
+            update.finish(status).await;
+            notify_update(config, NotifyEvent::Succeeded, context, None, status).await;
+            Ok(())
+        }
+        Err(error) => {
+            // Before the `on_failure` hook, so that the log tail is that of
+            // the step that failed.
+            notify_update(config, NotifyEvent::Failed, context, Some(&error), status).await;
+            handle_failed_update(config, context, status, rollback, &update).await;
+            update.finish(status).await;
+            Err(error)
+        }
+    }
+}
+
+/// `hook` if given, or else whatever the top-level configuration runs for an
+/// update. `args` are only passed to hooks.
+fn pipeline<'a>(
+    config: &'a Config,
+    hook: Option<&'a HookCommand>,
+    args: &'a [&'a str],
+) -> Pipeline<'a> {
+    match hook.or(config.on_update.as_ref()) {
+        Some(command) => Pipeline::Hook {
+            command,
+            shell: config.shell_program(),
+            args,
+        },
+        None => Pipeline::Steps(&config.steps),
+    }
+}
+
+/// The commit of the last successful update of the branch or tag of
+/// `context`, or else the commit before it.
+async fn good_commit(status: &StatusRecorder, context: &UpdateContext) -> Option<String> {
+    status
+        .read()
+        .await
+        .good_commit(&context.reference())
+        .map(String::from)
+        .or_else(|| context.old_commit.clone())
+}
+
+/// Roll back `rollback` if given, and run the `on_failure` hook, after an
+/// update has failed.
+///
+/// Failures are only logged, so that the error of the update is reported.
+async fn handle_failed_update(
+    config: &Config,
+    context: &UpdateContext,
+    status: &StatusRecorder,
+    rollback: Option<&Repository>,
+    update: &RecordedUpdate,
+) {
+    if let Some(repo) = rollback {
+        if let Err(error) = roll_back(repo, context) {
+            tracing::error!(?error, "Failed to roll back");
+        }
+    }
+
+    if let Some(hook) = &config.on_failure {
+        if let Err(error) = run_failure_hook(
+            hook,
+            config.shell_program(),
+            context,
+            config.hook_timeout(),
+            status,
+            update,
+        )
+        .await
+        {
+            tracing::error!(?error, "The `on_failure` hook failed");
+        }
+    }
+}
+
+/// Reset the branch that HEAD points to, or HEAD itself if it is detached,
+/// and the working tree, to the good commit of `context`.
+fn roll_back(repo: &Repository, context: &UpdateContext) -> Result<(), GitError> {
+    let Some(good_commit) = &context.good_commit else {
+        tracing::warn!("No successful update is known. Not rolling back.");
+        return Ok(());
+    };
+    let good_commit = ObjectId::from_hex(good_commit.as_bytes())
+        .map_err(|_| GitError::InvalidCommitId(good_commit.clone()))?;
+
+    let current_commit_id = repo.current_commit_id()?;
+    if current_commit_id == good_commit {
+        return Ok(());
+    }
+
+    let branch = repo.head_branch()?;
+    repo.checkout(branch.as_ref(), current_commit_id, good_commit)?;
+
+    tracing::info!(
+        "Rolled back `{}` to {good_commit}.",
+        branch.as_ref().map_or("HEAD", Branch::short_name)
+    );
 
     Ok(())
 }
+
+async fn save_seen_ref(
+    status: &StatusRecorder,
+    remote: &RemoteRepository,
+    full_name: &str,
+    id: ObjectId,
+) {
+    let seen_ref = SeenRef {
+        url: remote.url().to_string(),
+        reference: full_name.to_string(),
+        id: id.to_string(),
+    };
+    status.update(|s| s.set_seen_ref(seen_ref)).await;
+}
+
+async fn save_seen_branches(status: &StatusRecorder, seen: &BTreeMap<String, ObjectId>) {
+    let branches = seen
+        .iter()
+        .map(|(name, id)| (name.clone(), id.to_string()))
+        .collect();
+    status.update(|s| s.set_seen_branches(branches)).await;
+}
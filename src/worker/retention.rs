@@ -0,0 +1,243 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::config::RetentionConfig;
+
+struct UpdateDirectory {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+/// Delete the directories under `updates` that exceed the limits of
+/// `retention`, starting with the oldest. `keep` is never deleted.
+///
+/// Failing to delete a directory is logged, but does not stop the worker.
+pub fn prune(updates: &Path, retention: &RetentionConfig, keep: Option<&Path>) {
+    let directories = match list(updates) {
+        Ok(directories) => directories,
+        Err(error) => {
+            tracing::warn!(?error, "Failed to list `{}`", updates.display());
+            return;
+        }
+    };
+
+    prune_directories(&directories, retention, keep, SystemTime::now(), |path| {
+        fs::remove_dir_all(path)
+    });
+}
+
+/// Like [`prune`], but for `directories`, oldest first, which are deleted
+/// with `remove`.
+fn prune_directories(
+    directories: &[UpdateDirectory],
+    retention: &RetentionConfig,
+    keep: Option<&Path>,
+    now: SystemTime,
+    mut remove: impl FnMut(&Path) -> Result<(), io::Error>,
+) {
+    let mut total_size = 0;
+
+    // Newest first, so that the limits are filled up with the most recent
+    // updates.
+    for (index, directory) in directories.iter().rev().enumerate() {
+        if keep == Some(directory.path.as_path()) {
+            total_size += directory.size;
+            continue;
+        }
+
+        let too_many = retention.keep_last.map_or(false, |n| index >= n);
+        let too_old = retention.max_age.map_or(false, |max_age| {
+            now.duration_since(directory.modified)
+                .map_or(false, |age| age > max_age.as_duration())
+        });
+        let too_large = retention
+            .max_total_size
+            .map_or(false, |max| total_size + directory.size > max.as_bytes());
+
+        if !(too_many || too_old || too_large) {
+            total_size += directory.size;
+            continue;
+        }
+
+        tracing::debug!("Removing `{}`", directory.path.display());
+
+        if let Err(error) = remove(&directory.path) {
+            tracing::warn!(?error, "Failed to remove `{}`", directory.path.display());
+            total_size += directory.size;
+        }
+    }
+}
+
+/// Every directory under `updates`, oldest first.
+///
+/// Update directories are named after the time they were created, so sorting
+/// them by name sorts them by age.
+fn list(updates: &Path) -> Result<Vec<UpdateDirectory>, io::Error> {
+    let mut directories = Vec::new();
+
+    for entry in fs::read_dir(updates)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_dir() {
+            continue;
+        }
+
+        let path = entry.path();
+        directories.push(UpdateDirectory {
+            size: size(&path)?,
+            modified: metadata.modified()?,
+            path,
+        });
+    }
+
+    directories.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(directories)
+}
+
+fn size(path: &Path) -> Result<u64, io::Error> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            self::size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::units::{Age, Size};
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Directories named `1` to `n`, oldest first, `n - i` days old and
+    /// `size` bytes large.
+    fn directories(n: u64, size: u64, now: SystemTime) -> Vec<UpdateDirectory> {
+        (1..=n)
+            .map(|i| UpdateDirectory {
+                path: PathBuf::from(i.to_string()),
+                modified: now - DAY * (n - i) as u32,
+                size,
+            })
+            .collect()
+    }
+
+    /// The names of the directories that are removed, newest first.
+    fn removed(
+        directories: &[UpdateDirectory],
+        retention: &RetentionConfig,
+        keep: Option<&str>,
+        now: SystemTime,
+    ) -> Vec<String> {
+        let mut removed = Vec::new();
+        prune_directories(directories, retention, keep.map(Path::new), now, |path| {
+            removed.push(path.display().to_string());
+            Ok(())
+        });
+        removed
+    }
+
+    #[test]
+    fn keeps_everything_without_limits() {
+        let now = SystemTime::now();
+        let retention = RetentionConfig::default();
+        assert!(removed(&directories(5, 10, now), &retention, None, now).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_last_n() {
+        let now = SystemTime::now();
+        let retention = RetentionConfig {
+            keep_last: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            removed(&directories(5, 10, now), &retention, None, now),
+            ["3", "2", "1"]
+        );
+    }
+
+    #[test]
+    fn keep_last_zero_removes_all_but_keep() {
+        let now = SystemTime::now();
+        let retention = RetentionConfig {
+            keep_last: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            removed(&directories(3, 10, now), &retention, Some("2"), now),
+            ["3", "1"]
+        );
+    }
+
+    #[test]
+    fn removes_directories_older_than_max_age() {
+        let now = SystemTime::now();
+        let retention = RetentionConfig {
+            max_age: Some(Age::try_from("2d".to_string()).unwrap()),
+            ..Default::default()
+        };
+        // Ages are 4, 3, 2, 1 and 0 days. Exactly 2 days is not too old.
+        assert_eq!(
+            removed(&directories(5, 10, now), &retention, None, now),
+            ["2", "1"]
+        );
+    }
+
+    #[test]
+    fn removes_oldest_directories_over_max_total_size() {
+        let now = SystemTime::now();
+        let retention = RetentionConfig {
+            max_total_size: Some(serde_json::from_str::<Size>("25").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            removed(&directories(5, 10, now), &retention, None, now),
+            ["3", "2", "1"]
+        );
+    }
+
+    #[test]
+    fn never_removes_keep() {
+        let now = SystemTime::now();
+        let retention = RetentionConfig {
+            keep_last: Some(1),
+            max_age: Some(Age::try_from("1d".to_string()).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            removed(&directories(4, 10, now), &retention, Some("1"), now),
+            ["3", "2"]
+        );
+    }
+
+    #[test]
+    fn counts_directories_that_failed_to_be_removed() {
+        let now = SystemTime::now();
+        let retention = RetentionConfig {
+            max_total_size: Some(serde_json::from_str::<Size>("20").unwrap()),
+            ..Default::default()
+        };
+        let mut attempted = Vec::new();
+        prune_directories(&directories(4, 10, now), &retention, None, now, |path| {
+            attempted.push(path.display().to_string());
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"))
+        });
+        // `2` still takes up space, so `1` is over the limit as well.
+        assert_eq!(attempted, ["2", "1"]);
+    }
+}
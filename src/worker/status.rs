@@ -0,0 +1,159 @@
+use std::{cell::Cell, path::PathBuf};
+
+use crate::database::{self, update_key, Status, Update, STATUSES, UPDATES};
+
+/// Records the status of this worker in the database.
+///
+/// The database is only opened for every access, so that the CLI can open it
+/// in between. Waiting for it to be unlocked does not block the runtime.
+///
+/// Failing to record the status is logged, but does not stop the worker.
+pub struct StatusRecorder {
+    database_dir: Option<PathBuf>,
+    key: String,
+    /// The ID of the update that was started last.
+    last_update_id: Cell<Option<u64>>,
+}
+
+impl StatusRecorder {
+    pub fn new(key: String) -> Self {
+        let database_dir = home::home_dir().map(|home| home.join(".outpost").join("database"));
+
+        if database_dir.is_none() {
+            tracing::warn!("Home directory not found. The worker status will not be recorded.");
+        }
+
+        Self {
+            database_dir,
+            key,
+            last_update_id: Cell::new(None),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn last_update_id(&self) -> Option<u64> {
+        self.last_update_id.get()
+    }
+
+    pub async fn read(&self) -> Status {
+        let Some(database_dir) = &self.database_dir else {
+            return Status::default();
+        };
+
+        let result = database::open_async(database_dir)
+            .await
+            .and_then(|db| db.open_tree(STATUSES))
+            .and_then(|statuses| statuses.get(self.key.as_bytes()));
+
+        match result {
+            Ok(value) => value
+                .map(|value| Status::from_slice(value.as_ref()).expect("valid json"))
+                .unwrap_or_default(),
+            Err(error) => {
+                tracing::warn!(?error, "Failed to read the worker status");
+                Status::default()
+            }
+        }
+    }
+
+    pub async fn update(&self, f: impl FnOnce(&mut Status)) {
+        let Some(database_dir) = &self.database_dir else {
+            return;
+        };
+
+        let result = database::open_async(database_dir)
+            .await
+            .and_then(|db| db.open_tree(STATUSES))
+            .and_then(|statuses| {
+                let mut status = statuses
+                    .get(self.key.as_bytes())?
+                    .map(|value| Status::from_slice(value.as_ref()).expect("valid json"))
+                    .unwrap_or_default();
+
+                f(&mut status);
+
+                statuses.insert(
+                    self.key.as_bytes(),
+                    serde_json::to_vec(&status).expect("failed to serialize status"),
+                )?;
+                statuses.flush().map(|_| ())
+            });
+
+        if let Err(error) = result {
+            tracing::warn!(?error, "Failed to record the worker status");
+        }
+    }
+
+    /// Add `update` to the update history, and return its ID.
+    pub async fn start_update(&self, update: Update) -> Option<u64> {
+        let database_dir = self.database_dir.as_ref()?;
+
+        let result = database::open_async(database_dir).await.and_then(|db| {
+            let id = db.generate_id()?;
+            let updates = db.open_tree(UPDATES)?;
+            updates.insert(
+                update_key(id),
+                serde_json::to_vec(&update).expect("failed to serialize update"),
+            )?;
+            updates.flush().map(|_| id)
+        });
+
+        let id = match result {
+            Ok(id) => Some(id),
+            Err(error) => {
+                tracing::warn!(?error, "Failed to record the update");
+                None
+            }
+        };
+        self.last_update_id.set(id);
+        id
+    }
+
+    pub async fn read_update(&self, id: u64) -> Option<Update> {
+        let database_dir = self.database_dir.as_ref()?;
+
+        let result = database::open_async(database_dir)
+            .await
+            .and_then(|db| db.open_tree(UPDATES))
+            .and_then(|updates| updates.get(update_key(id)));
+
+        match result {
+            Ok(value) => value.map(|value| Update::from_slice(value.as_ref()).expect("valid json")),
+            Err(error) => {
+                tracing::warn!(?error, "Failed to read the update");
+                None
+            }
+        }
+    }
+
+    pub async fn modify_update(&self, id: u64, f: impl FnOnce(&mut Update)) {
+        let Some(database_dir) = &self.database_dir else {
+            return;
+        };
+
+        let result = database::open_async(database_dir)
+            .await
+            .and_then(|db| db.open_tree(UPDATES))
+            .and_then(|updates| {
+                let Some(value) = updates.get(update_key(id))? else {
+                    return Ok(());
+                };
+                let mut update = Update::from_slice(value.as_ref()).expect("valid json");
+
+                f(&mut update);
+
+                updates.insert(
+                    update_key(id),
+                    serde_json::to_vec(&update).expect("failed to serialize update"),
+                )?;
+                updates.flush().map(|_| ())
+            });
+
+        if let Err(error) = result {
+            tracing::warn!(?error, "Failed to record the update");
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use std::{convert::Infallible, sync::Arc};
+
+use hmac::{Hmac, Mac};
+use hyper::{
+    body::HttpBody,
+    service::{make_service_fn, service_fn},
+    Body, HeaderMap, Method, Request, Response, Server, StatusCode,
+};
+use sha2::Sha256;
+use tokio::sync::Notify;
+
+use super::poll::PollError;
+use crate::config::ListenConfig;
+
+/// Push payloads are far smaller than this. Larger requests are rejected
+/// before they are read completely.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+struct Webhook {
+    path: String,
+    secret: String,
+    pushed: Arc<Notify>,
+}
+
+/// Start receiving webhooks in the background. `pushed` is notified for every
+/// verified push.
+///
+/// Must be called from within the runtime.
+pub fn serve(config: &ListenConfig, pushed: Arc<Notify>) -> Result<(), PollError> {
+    let builder = Server::try_bind(&config.address).map_err(PollError::Listen)?;
+
+    let webhook = Arc::new(Webhook {
+        path: config.path.clone(),
+        secret: config.secret.clone(),
+        pushed,
+    });
+    let make_service = make_service_fn(move |_| {
+        let webhook = webhook.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(webhook.clone(), request))) }
+    });
+
+    let server = builder.serve(make_service);
+    tracing::info!("Listening for webhooks on `{}`.", server.local_addr());
+
+    tokio::spawn(async move {
+        if let Err(error) = server.await {
+            tracing::error!(?error, "The webhook server stopped");
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle(
+    webhook: Arc<Webhook>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != webhook.path {
+        return Ok(respond(StatusCode::NOT_FOUND));
+    }
+    if request.method() != Method::POST {
+        return Ok(respond(StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = match read_body(body).await {
+        Ok(body) => body,
+        Err(status) => return Ok(respond(status)),
+    };
+
+    if !verify(&parts.headers, &body, webhook.secret.as_bytes()) {
+        tracing::warn!("Rejected a webhook without a valid signature or token.");
+        return Ok(respond(StatusCode::UNAUTHORIZED));
+    }
+
+    if !is_push(&parts.headers) {
+        // E.g. the `ping` that GitHub sends when the webhook is created.
+        tracing::debug!("Ignored a webhook that is not a push.");
+        return Ok(respond(StatusCode::NO_CONTENT));
+    }
+
+    tracing::info!("Received a push webhook.");
+    webhook.pushed.notify_one();
+
+    Ok(respond(StatusCode::ACCEPTED))
+}
+
+async fn read_body(mut body: Body) -> Result<Vec<u8>, StatusCode> {
+    let mut bytes = Vec::new();
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if bytes.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+fn respond(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// GitHub and Gitea sign the body with the secret, GitLab sends the secret
+/// itself as a token.
+fn verify(headers: &HeaderMap, body: &[u8], secret: &[u8]) -> bool {
+    if let Some(signature) = header(headers, "x-hub-signature-256") {
+        signature
+            .strip_prefix("sha256=")
+            .map_or(false, |signature| verify_signature(signature, body, secret))
+    } else if let Some(signature) = header(headers, "x-gitea-signature") {
+        verify_signature(signature, body, secret)
+    } else if let Some(token) = header(headers, "x-gitlab-token") {
+        constant_time_eq(token.as_bytes(), secret)
+    } else {
+        false
+    }
+}
+
+/// `signature` is the hex encoded HMAC-SHA256 of `body`.
+fn verify_signature(signature: &str, body: &[u8], secret: &[u8]) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Pushes of branches and tags. Gitea also sends the GitHub header, so its
+/// own header is checked first.
+fn is_push(headers: &HeaderMap) -> bool {
+    if let Some(event) = header(headers, "x-gitlab-event") {
+        return matches!(event, "Push Hook" | "Tag Push Hook");
+    }
+
+    header(headers, "x-gitea-event").or_else(|| header(headers, "x-github-event")) == Some("push")
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::HeaderValue;
+
+    use super::*;
+
+    const SECRET: &[u8] = b"It's a Secret to Everybody";
+    const BODY: &[u8] = b"Hello, World!";
+    /// From the GitHub documentation on validating webhook deliveries.
+    const GITHUB_SIGNATURE: &str =
+        "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn sign(body: &[u8], secret: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn accepts_github_signature() {
+        let headers = headers(&[("x-hub-signature-256", GITHUB_SIGNATURE)]);
+        assert!(verify(&headers, BODY, SECRET));
+    }
+
+    #[test]
+    fn rejects_github_signature_for_other_body_or_secret() {
+        let headers = headers(&[("x-hub-signature-256", GITHUB_SIGNATURE)]);
+        assert!(!verify(&headers, b"Hello, World?", SECRET));
+        assert!(!verify(&headers, BODY, b"another secret"));
+    }
+
+    #[test]
+    fn rejects_malformed_github_signature() {
+        let unprefixed = GITHUB_SIGNATURE.strip_prefix("sha256=").unwrap();
+        let truncated = &GITHUB_SIGNATURE[..GITHUB_SIGNATURE.len() - 2];
+        for signature in [unprefixed, truncated, "sha256=", "sha256=zz", "sha1=00"] {
+            let headers = headers(&[("x-hub-signature-256", signature)]);
+            assert!(!verify(&headers, BODY, SECRET), "{signature}");
+        }
+    }
+
+    #[test]
+    fn accepts_gitea_signature() {
+        let signature = sign(BODY, SECRET);
+        assert!(verify(
+            &headers(&[("x-gitea-signature", &signature)]),
+            BODY,
+            SECRET
+        ));
+        assert!(!verify(
+            &headers(&[("x-gitea-signature", &signature)]),
+            BODY,
+            b"another secret"
+        ));
+        assert!(!verify(
+            &headers(&[("x-gitea-signature", "not hex")]),
+            BODY,
+            SECRET
+        ));
+    }
+
+    #[test]
+    fn accepts_gitlab_token() {
+        let secret = std::str::from_utf8(SECRET).unwrap();
+        assert!(verify(
+            &headers(&[("x-gitlab-token", secret)]),
+            BODY,
+            SECRET
+        ));
+        assert!(!verify(
+            &headers(&[("x-gitlab-token", "It's a Secret to Everybodx")]),
+            BODY,
+            SECRET
+        ));
+        assert!(!verify(
+            &headers(&[("x-gitlab-token", "It's a Secret")]),
+            BODY,
+            SECRET
+        ));
+        assert!(!verify(&headers(&[("x-gitlab-token", "")]), BODY, SECRET));
+    }
+
+    #[test]
+    fn rejects_requests_without_signature_or_token() {
+        assert!(!verify(&HeaderMap::new(), BODY, SECRET));
+        assert!(!verify(
+            &headers(&[("x-hub-signature", "sha1=00")]),
+            BODY,
+            SECRET
+        ));
+    }
+
+    #[test]
+    fn recognizes_pushes() {
+        assert!(is_push(&headers(&[("x-github-event", "push")])));
+        assert!(is_push(&headers(&[("x-gitea-event", "push")])));
+        assert!(is_push(&headers(&[("x-gitlab-event", "Push Hook")])));
+        assert!(is_push(&headers(&[("x-gitlab-event", "Tag Push Hook")])));
+
+        assert!(!is_push(&headers(&[("x-github-event", "ping")])));
+        assert!(!is_push(&headers(&[(
+            "x-gitlab-event",
+            "Merge Request Hook"
+        )])));
+        assert!(!is_push(&HeaderMap::new()));
+        // Gitea sends both headers, its own takes precedence.
+        assert!(!is_push(&headers(&[
+            ("x-gitea-event", "create"),
+            ("x-github-event", "push"),
+        ])));
+    }
+
+    fn webhook() -> Arc<Webhook> {
+        Arc::new(Webhook {
+            path: "/hooks/outpost".to_string(),
+            secret: std::str::from_utf8(SECRET).unwrap().to_string(),
+            pushed: Arc::new(Notify::new()),
+        })
+    }
+
+    fn request(
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Request<Body> {
+        let mut builder = Request::builder().method(method).uri(path);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::from(body)).unwrap()
+    }
+
+    async fn status(webhook: Arc<Webhook>, request: Request<Body>) -> StatusCode {
+        handle(webhook, request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn notifies_for_verified_pushes() {
+        let webhook = webhook();
+        let request = request(
+            Method::POST,
+            "/hooks/outpost",
+            &[
+                ("x-hub-signature-256", GITHUB_SIGNATURE),
+                ("x-github-event", "push"),
+            ],
+            BODY.to_vec(),
+        );
+
+        assert_eq!(status(webhook.clone(), request).await, StatusCode::ACCEPTED);
+        // The permit stored by `notify_one` completes this right away.
+        tokio::time::timeout(std::time::Duration::from_secs(1), webhook.pushed.notified())
+            .await
+            .expect("push was not notified");
+    }
+
+    #[tokio::test]
+    async fn ignores_verified_requests_that_are_not_pushes() {
+        let request = request(
+            Method::POST,
+            "/hooks/outpost",
+            &[
+                ("x-hub-signature-256", GITHUB_SIGNATURE),
+                ("x-github-event", "ping"),
+            ],
+            BODY.to_vec(),
+        );
+        assert_eq!(status(webhook(), request).await, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn rejects_unsigned_or_badly_signed_requests() {
+        let unsigned = request(
+            Method::POST,
+            "/hooks/outpost",
+            &[("x-github-event", "push")],
+            BODY.to_vec(),
+        );
+        assert_eq!(status(webhook(), unsigned).await, StatusCode::UNAUTHORIZED);
+
+        let badly_signed = request(
+            Method::POST,
+            "/hooks/outpost",
+            &[
+                ("x-hub-signature-256", GITHUB_SIGNATURE),
+                ("x-github-event", "push"),
+            ],
+            b"tampered".to_vec(),
+        );
+        assert_eq!(
+            status(webhook(), badly_signed).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_other_paths_methods_and_large_bodies() {
+        let other_path = request(Method::POST, "/", &[], Vec::new());
+        assert_eq!(status(webhook(), other_path).await, StatusCode::NOT_FOUND);
+
+        let get = request(Method::GET, "/hooks/outpost", &[], Vec::new());
+        assert_eq!(status(webhook(), get).await, StatusCode::METHOD_NOT_ALLOWED);
+
+        let large = request(
+            Method::POST,
+            "/hooks/outpost",
+            &[],
+            vec![b'x'; MAX_BODY_SIZE + 1],
+        );
+        assert_eq!(
+            status(webhook(), large).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+}
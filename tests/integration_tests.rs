@@ -1 +1,122 @@
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
+use outpost::{config::SshConfig, git::RemoteRepository};
+
+/// A fresh directory under the system temporary directory.
+fn temp_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("outpost-{name}-{}", std::process::id()));
+    if path.exists() {
+        fs::remove_dir_all(&path).unwrap();
+    }
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn git(directory: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args([
+            "-c",
+            "user.name=outpost",
+            "-c",
+            "user.email=outpost@example.com",
+        ])
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .expect("failed to run git");
+    assert!(output.status.success(), "git {args:?} failed: {output:?}");
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// Runs `fetch_through_the_configured_ssh_command` in a process of its own,
+/// as `SshConfig::apply` changes the environment, which is not safe while
+/// other tests run.
+#[test]
+fn fetches_through_the_configured_ssh_command() {
+    let status = Command::new(std::env::current_exe().unwrap())
+        .args([
+            "fetch_through_the_configured_ssh_command",
+            "--exact",
+            "--ignored",
+            "--test-threads=1",
+        ])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// Fetches from an `ssh://` URL through a stand-in for `ssh`, which records
+/// its arguments and serves the repository with the local `git-upload-pack`.
+#[test]
+#[ignore = "run by `fetches_through_the_configured_ssh_command`"]
+fn fetch_through_the_configured_ssh_command() {
+    let root = temp_dir("ssh");
+
+    let upstream = root.join("upstream");
+    fs::create_dir(&upstream).unwrap();
+    git(
+        &upstream,
+        &["-c", "init.defaultBranch=main", "init", "--quiet"],
+    );
+    git(
+        &upstream,
+        &["commit", "--quiet", "--allow-empty", "-m", "init"],
+    );
+    let commit = git(&upstream, &["rev-parse", "HEAD"]);
+
+    // Named `ssh` like the real one. The last argument is the command to run
+    // on the remote, e.g. `git-upload-pack '/path'`.
+    let bin = root.join("bin");
+    fs::create_dir(&bin).unwrap();
+    let stub = bin.join("ssh");
+    let args_file = root.join("args");
+    fs::write(
+        &stub,
+        format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$@\" > '{}'\nfor last; do :; done\nexec sh -c \"$last\"\n",
+            args_file.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let identity_file = root.join("id_ed25519");
+    fs::write(&identity_file, "not a key").unwrap();
+    let known_hosts = root.join("known_hosts");
+    fs::write(&known_hosts, "").unwrap();
+
+    SshConfig {
+        command: Some(stub.display().to_string()),
+        identity_file: Some(identity_file.clone()),
+        known_hosts: Some(known_hosts.clone()),
+    }
+    .apply();
+
+    let url = format!("ssh://outpost.invalid{}", upstream.display());
+    let remote = RemoteRepository::open(&root.join("scratch"), url).unwrap();
+    let id = remote.ls_remote("refs/heads/main", None).unwrap();
+
+    assert_eq!(id.map(|id| id.to_string()), Some(commit));
+
+    let args = fs::read_to_string(&args_file).expect("the ssh command was not run");
+    let args: Vec<&str> = args.lines().collect();
+    let expected = [
+        "BatchMode=yes",
+        "-i",
+        identity_file.to_str().unwrap(),
+        "IdentitiesOnly=yes",
+        &format!("UserKnownHostsFile={}", known_hosts.display()),
+        "StrictHostKeyChecking=yes",
+        "outpost.invalid",
+    ];
+    for arg in expected {
+        assert!(args.contains(&arg), "`{arg}` missing from {args:?}");
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}